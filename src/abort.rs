@@ -0,0 +1,220 @@
+//! Aborting replays with the panic key, and shutting down cleanly on SIGINT and SIGTERM.
+//!
+//! libinput events are only read between replays, so the panic key is watched on a thread that
+//! reads the evdev devices directly and raises a flag the replay checks between events. The
+//! signals raise the same flag, and another one that makes the event loops return, so the uinput
+//! device is dropped and releases the keys it holds.
+use hotkey::{Hotkey, ModifierState};
+use libc;
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use timer;
+use uinput::{self, EventType, UInput};
+
+/// Longest time to sleep without checking for an abort.
+const CHECK_INTERVAL_MS: u32 = 10;
+
+static ABORTED: AtomicBool = AtomicBool::new(false);
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+/// Signals that end swan-ag.
+const TERMINATION_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+/// Returns whether an abort was requested since the last reset.
+pub fn requested() -> bool {
+    ABORTED.load(Ordering::SeqCst)
+}
+
+pub fn request() {
+    ABORTED.store(true, Ordering::SeqCst);
+}
+
+/// Forget earlier abort requests, before starting a replay. Termination can't be forgotten.
+pub fn reset() {
+    ABORTED.store(terminating(), Ordering::SeqCst);
+}
+
+/// Returns whether SIGINT or SIGTERM was received.
+pub fn terminating() -> bool {
+    TERMINATING.load(Ordering::SeqCst)
+}
+
+extern fn handle_termination(_signal: libc::c_int) {
+    TERMINATING.store(true, Ordering::SeqCst);
+    ABORTED.store(true, Ordering::SeqCst);
+}
+
+/// Abort replays and mark the program as terminating on SIGINT and SIGTERM, instead of being
+/// killed. The signals interrupt blocking calls like poll, so event loops can notice.
+pub fn handle_termination_signals() {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle_termination as extern fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        for &signal in &TERMINATION_SIGNALS {
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+/// Keep the termination signals from being delivered to the calling thread, so they interrupt
+/// the main thread instead.
+fn block_termination_signals() {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for &signal in &TERMINATION_SIGNALS {
+            libc::sigaddset(&mut set, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+    }
+}
+
+/// Sleep until the monotonic clock reaches the given time like timer::sleep_until, but return
+/// early if an abort is requested. `poll` is called whenever that is checked, so work like reading
+/// input can go on while sleeping. Returns whether the deadline was reached.
+pub fn sleep_until_polling<F>(deadline: Duration, mut poll: F) -> bool
+    where F: FnMut()
+{
+    sleep_until_or(deadline, || {
+        poll();
+        requested()
+    })
+}
+
+/// Like sleep_until_polling without polling, but only return early on SIGINT or SIGTERM, for waits that the panic key
+/// shouldn't cut short.
+pub fn sleep_until_terminating(deadline: Duration) -> bool {
+    sleep_until_or(deadline, terminating)
+}
+
+fn sleep_until_or<F>(deadline: Duration, mut stop: F) -> bool
+    where F: FnMut() -> bool
+{
+    loop {
+        if stop() {
+            return false;
+        }
+
+        let now = timer::monotonic_now();
+        if now >= deadline {
+            return true;
+        }
+        timer::sleep_until(cmp::min(deadline, now + Duration::new(0, CHECK_INTERVAL_MS * 1_000_000)));
+    }
+}
+
+/// Returns every evdev device node except the one of swan-ag's own device, for when libinput can't
+/// tell which devices to watch. Without a known event node the own device is left out by name.
+pub fn all_devices(own: &UInput) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+        let node = match path.file_name().and_then(|name| name.to_str()) {
+            Some(node) if node.starts_with("event") => node.to_string(),
+            _ => continue,
+        };
+
+        let is_own = match own.devnode() {
+            Some(devnode) => devnode == path.as_path(),
+            None => {
+                let mut name = String::new();
+                File::open(format!("/sys/class/input/{}/device/name", node))
+                    .and_then(|mut file| file.read_to_string(&mut name))
+                    .ok();
+                name.trim() == own.name()
+            },
+        };
+        if !is_own {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Start watching the given evdev devices for the panic key, which requests an abort when pressed.
+pub fn watch_panic_key(panic_key: Hotkey, paths: &[PathBuf]) -> io::Result<()> {
+    let mut devices = Vec::new();
+    for path in paths {
+        // Devices we may not read can't be watched, which is fine as long as the keyboard can.
+        if let Ok(device) = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path) {
+            devices.push(device);
+        }
+    }
+    if devices.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "no readable input devices"));
+    }
+
+    thread::spawn(move || watch(panic_key, devices));
+    Ok(())
+}
+
+fn watch(panic_key: Hotkey, mut devices: Vec<File>) {
+    block_termination_signals();
+    let mut modifiers = ModifierState::default();
+
+    while !devices.is_empty() {
+        let mut fds: Vec<libc::pollfd> = devices.iter().map(|device| {
+            libc::pollfd { fd: device.as_raw_fd(), events: libc::POLLIN, revents: 0 }
+        }).collect();
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            continue;
+        }
+
+        // Walk backwards so unplugged devices can be removed as we go.
+        for i in (0..devices.len()).rev() {
+            if fds[i].revents == 0 {
+                continue;
+            }
+            if read_keys(&mut devices[i], &panic_key, &mut modifiers).is_err() {
+                devices.remove(i);
+            }
+        }
+    }
+}
+
+/// Read the pending events of a device, requesting an abort if the panic key was pressed.
+fn read_keys(device: &mut File, panic_key: &Hotkey, modifiers: &mut ModifierState) -> io::Result<()> {
+    let mut events: [uinput::input_event; 64] = unsafe { mem::zeroed() };
+    let size = mem::size_of::<uinput::input_event>();
+
+    loop {
+        let n = {
+            let buf = unsafe { slice::from_raw_parts_mut(events.as_mut_ptr() as *mut u8, events.len() * size) };
+            match device.read(buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input device closed"));
+        }
+
+        for event in &events[..n / size] {
+            // Key repeats (value 2) don't change what's held.
+            if event.kind != EventType::EV_KEY as u16 || event.value == 2 {
+                continue;
+            }
+            if let Ok(key) = uinput::Key::from_code(event.code) {
+                let pressed = event.value == 1;
+                modifiers.update(key, pressed);
+                if pressed && panic_key.matches(key, modifiers) {
+                    request();
+                }
+            }
+        }
+    }
+}
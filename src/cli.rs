@@ -0,0 +1,457 @@
+//! Command line parsing.
+use argparse::{ArgumentParser, Collect, List, Store, StoreOption, StoreTrue};
+use config::Config;
+use device::DeviceFilter;
+use hotkey::Hotkey;
+use library::SlotBinding;
+use pointer::ScreenSize;
+use replay::IdleTime;
+use std::io::{stderr, stdout};
+use std::process;
+use std::time::Duration;
+use timer;
+use uinput::{self, InputId, Key, Property};
+
+/// What this invocation of swan-ag should do.
+pub enum Command {
+    /// Record and replay with hotkeys until killed.
+    Interactive,
+    /// Record once and save the recording to the given file.
+    Record(String),
+    /// Replay the given recording file.
+    Play(String),
+    /// Print the events of the given recording file.
+    Inspect(String),
+    /// Print the input devices libinput can see.
+    ListDevices,
+}
+
+/// Command line options for this program.
+pub struct Options {
+    pub command: Command,
+    pub speed: f64,
+    pub lead_in: IdleTime,
+    pub tail: IdleTime,
+    pub repeat: u32,
+    pub loop_replay: bool,
+    pub repeat_delay: f64,
+    pub abort_key: Hotkey,
+    pub panic_key: Hotkey,
+    pub record_delay: Option<f64>,
+    pub bell: bool,
+    pub notify_command: Option<String>,
+    pub record_length: Option<f64>,
+    pub input_file: Option<String>,
+    pub output_file: Option<String>,
+    pub record_key: Hotkey,
+    pub replay_key: Hotkey,
+    pub library: Option<String>,
+    pub slots: Vec<SlotBinding>,
+    pub select: Option<String>,
+    pub absolute: bool,
+    pub screen: Option<ScreenSize>,
+    pub seat: String,
+    pub devices: Vec<DeviceFilter>,
+    pub device_name: String,
+    pub device_id: InputId,
+    pub device_props: Vec<Property>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            command: Command::Interactive,
+            speed: 1.0,
+            lead_in: IdleTime::Fixed(Duration::new(0, 0)),
+            tail: IdleTime::Fixed(Duration::new(0, 0)),
+            repeat: 1,
+            loop_replay: false,
+            repeat_delay: 0.0,
+            abort_key: Hotkey::new(Key::Pause),
+            panic_key: Hotkey::new(Key::ScrollLock),
+            record_delay: None,
+            bell: false,
+            notify_command: None,
+            record_length: None,
+            input_file: None,
+            output_file: None,
+            record_key: Hotkey::new(Key::Esc),
+            replay_key: Hotkey::new(Key::F2),
+            library: None,
+            slots: Vec::new(),
+            select: None,
+            absolute: false,
+            screen: None,
+            seat: "seat0".to_string(),
+            devices: Vec::new(),
+            device_name: "swan-ag virtual input".to_string(),
+            device_id: InputId {
+                bustype: uinput::BUS_VIRTUAL,
+                vendor: 0,
+                product: 0,
+                version: 1,
+            },
+            device_props: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Apply the settings from the config file.
+    fn apply_config(&mut self, config: &Config) {
+        if let Some(record_key) = config.get("record_key") {
+            self.record_key = record_key;
+        }
+        if let Some(replay_key) = config.get("replay_key") {
+            self.replay_key = replay_key;
+        }
+        if let Some(lead_in) = config.get("lead_in") {
+            self.lead_in = lead_in;
+        }
+        if let Some(tail) = config.get("tail") {
+            self.tail = tail;
+        }
+        if let Some(repeat_delay) = config.get("repeat_delay") {
+            self.repeat_delay = repeat_delay;
+        }
+        if let Some(abort_key) = config.get("abort_key") {
+            self.abort_key = abort_key;
+        }
+        if let Some(panic_key) = config.get("panic_key") {
+            self.panic_key = panic_key;
+        }
+        if let Some(bell) = config.get("bell") {
+            self.bell = bell;
+        }
+        if let Some(notify_command) = config.get("notify") {
+            self.notify_command = Some(notify_command);
+        }
+        if let Some(library) = config.get("library") {
+            self.library = Some(library);
+        }
+        self.slots = config.get_all("slot");
+        if let Some(absolute) = config.get("absolute") {
+            self.absolute = absolute;
+        }
+        if let Some(screen) = config.get("screen") {
+            self.screen = Some(screen);
+        }
+        if let Some(device_name) = config.get("device_name") {
+            self.device_name = device_name;
+        }
+        if let Some(device_id) = config.get("device_id") {
+            self.device_id = device_id;
+        }
+        self.device_props = config.get_all("device_prop");
+        if let Some(seat) = config.get("seat") {
+            self.seat = seat;
+        }
+        self.devices = config.get_all("device");
+    }
+}
+
+/// Return the options struct based on command line arguments.
+pub fn parse_args() -> Options {
+    let mut options = Options::default();
+    options.apply_config(&Config::load());
+
+    let mut instant = false;
+    let mut command_name = String::new();
+    let mut args = Vec::new();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Record input events and replay them. Use the --delayed and --length option for buttonless recording.");
+        ap.refer(&mut options.absolute)
+          .add_option(&["-a", "--absolute"], StoreTrue,
+                      "Record and replay pointer positions instead of relative motion. Requires --screen");
+        ap.refer(&mut options.record_delay)
+          .add_option(&["-d", "--delayed"], StoreOption,
+                      "Start recording after a number of seconds");
+        ap.refer(&mut options.bell)
+          .add_option(&["--bell"], StoreTrue,
+                      "Ring the terminal bell when recordings and replays start and stop, and during the --delayed countdown");
+        ap.refer(&mut options.notify_command)
+          .add_option(&["--notify"], StoreOption,
+                      "Shell command to run when recordings and replays start and stop, with SWAN_AG_EVENT set to record-start, record-stop, replay-start or replay-stop");
+        ap.refer(&mut options.input_file)
+          .add_option(&["-f", "--file"], StoreOption,
+                      "Load a saved recording to replay");
+        ap.refer(&mut instant)
+          .add_option(&["-i", "--instant"], StoreTrue,
+                      "Replay events with no delay between them");
+        ap.refer(&mut options.record_length)
+          .add_option(&["-l", "--length"], StoreOption,
+                      "Recordings will stop after a number of seconds");
+        /*
+        ap.refer(&mut options.no_flush)
+          .add_option(&["-n", "--no-flush"], StoreTrue,
+                      "Don't flush stored events between recordings");
+                      */
+        ap.refer(&mut options.record_key)
+          .add_option(&["-r", "--record-key"], Store,
+                      "Key that starts and stops recording, with optional modifiers (default: Esc)");
+        ap.refer(&mut options.replay_key)
+          .add_option(&["-p", "--replay-key"], Store,
+                      "Key that replays the selected slot, with optional modifiers (default: F2)");
+        ap.refer(&mut options.library)
+          .add_option(&["--library"], StoreOption,
+                      "Directory slots are saved in (default: $XDG_DATA_HOME/swan-ag/macros)");
+        ap.refer(&mut options.slots)
+          .add_option(&["--slot"], Collect,
+                      "Bind a slot to the hotkey that replays it, as name=hotkey. May be repeated (default: f1=Ctrl+F1 to f12=Ctrl+F12)");
+        ap.refer(&mut options.select)
+          .add_option(&["--select"], StoreOption,
+                      "Name of the slot selected at startup (default: the first slot)");
+        ap.refer(&mut options.output_file)
+          .add_option(&["-o", "--output"], StoreOption,
+                      "Save each recording to a file when it stops");
+        ap.refer(&mut options.speed)
+          .add_option(&["-s", "--speed"], Store,
+                      "Replay speed modifier (default: 1.0)");
+        ap.refer(&mut options.lead_in)
+          .add_option(&["--lead-in"], Store,
+                      "Seconds to wait before the first event of a replay, or original for the time from the start of the recording (default: 0)");
+        ap.refer(&mut options.tail)
+          .add_option(&["--tail"], Store,
+                      "Seconds to wait after the last event of a replay, or original for the time until the end of the recording (default: 0)");
+        ap.refer(&mut options.repeat)
+          .add_option(&["--repeat"], Store,
+                      "Replay a number of times (default: 1)");
+        ap.refer(&mut options.loop_replay)
+          .add_option(&["--loop"], StoreTrue,
+                      "Replay over and over until the abort key is pressed");
+        ap.refer(&mut options.repeat_delay)
+          .add_option(&["--repeat-delay"], Store,
+                      "Seconds to wait between repeated replays (default: 0)");
+        ap.refer(&mut options.abort_key)
+          .add_option(&["--abort-key"], Store,
+                      "Key that stops repeated replays, with optional modifiers (default: Pause)");
+        ap.refer(&mut options.panic_key)
+          .add_option(&["--panic-key"], Store,
+                      "Key that stops a replay immediately, with optional modifiers (default: ScrollLock)");
+        ap.refer(&mut options.screen)
+          .add_option(&["--screen"], StoreOption,
+                      "Size of the screen in pixels for --absolute, like 1920x1080");
+        ap.refer(&mut options.seat)
+          .add_option(&["--seat"], Store,
+                      "Seat whose devices are read (default: seat0)");
+        ap.refer(&mut options.devices)
+          .add_option(&["--device"], Collect,
+                      "Only record devices matching a name pattern like \"Logitech*\", a sysname, vendor:product, keyboard, pointer, touch or a /dev/input path. May be repeated (default: every device on the seat)");
+        ap.refer(&mut options.device_name)
+          .add_option(&["--device-name"], Store,
+                      "Name of the virtual device events are replayed with (default: swan-ag virtual input)");
+        ap.refer(&mut options.device_id)
+          .add_option(&["--device-id"], Store,
+                      "Bus type, vendor, product and optional version of the virtual device, in hex like 0003:046d:c52b (default: 0006:0000:0000:0001)");
+        ap.refer(&mut options.device_props)
+          .add_option(&["--device-prop"], Collect,
+                      "Input property of the virtual device, like pointer or direct. May be repeated");
+        ap.refer(&mut command_name)
+          .add_argument("command", Store,
+                        "One of record, play, inspect or list-devices. Runs interactively if omitted");
+        ap.refer(&mut args)
+          .add_argument("arguments", List,
+                        "Arguments for the command");
+        ap.stop_on_first_argument(true);
+        ap.parse_args_or_exit();
+    }
+
+    args.insert(0, format!("swan-ag {}", command_name));
+    let command = match command_name.as_str() {
+        "" => Command::Interactive,
+        "record" => parse_record_args(args, &mut options),
+        "play" => parse_play_args(args, &mut options, &mut instant),
+        "inspect" => parse_inspect_args(args),
+        "list-devices" => parse_list_devices_args(args, &mut options),
+        _ => {
+            eprintln!("Unknown command {}. Expected record, play, inspect or list-devices.", command_name);
+            process::exit(1);
+        },
+    };
+    options.command = command;
+
+    if !options.speed.is_finite() || options.speed <= 0.0 {
+        eprintln!("--speed must be a number above 0. Use --instant to replay without delays.");
+        process::exit(1);
+    }
+    if instant {
+        options.speed = 0.0;
+    }
+    if options.repeat == 0 {
+        eprintln!("--repeat must be at least 1.");
+        process::exit(1);
+    }
+    if !timer::is_valid_secs(options.repeat_delay) {
+        eprintln!("--repeat-delay must be a number of seconds.");
+        process::exit(1);
+    }
+    if !options.record_delay.map_or(true, timer::is_valid_secs) {
+        eprintln!("--delayed must be a number of seconds.");
+        process::exit(1);
+    }
+    if !options.record_length.map_or(true, timer::is_valid_secs) {
+        eprintln!("--length must be a number of seconds.");
+        process::exit(1);
+    }
+    if options.absolute && options.screen.is_none() {
+        eprintln!("--absolute requires --screen.");
+        process::exit(1);
+    }
+    let paths = options.devices.iter().filter(|d| match **d { DeviceFilter::Path(_) => true, _ => false }).count();
+    if paths != 0 && paths != options.devices.len() {
+        eprintln!("--device paths open only those devices and can't be combined with other device filters.");
+        process::exit(1);
+    }
+
+    // Later bindings, like those from the command line, replace earlier ones with the same name.
+    let mut slots: Vec<SlotBinding> = Vec::new();
+    for binding in options.slots.drain(..) {
+        slots.retain(|s| s.name != binding.name);
+        slots.push(binding);
+    }
+    options.slots = if slots.is_empty() { SlotBinding::defaults() } else { slots };
+
+    options
+}
+
+fn parse_record_args(args: Vec<String>, options: &mut Options) -> Command {
+    let mut output = String::new();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Record input events to a file. Recording stops after --length seconds or when the record key is pressed.");
+        ap.refer(&mut output)
+          .add_option(&["-o", "--output"], Store,
+                      "File to save the recording to")
+          .required();
+        ap.refer(&mut options.record_delay)
+          .add_option(&["-d", "--delayed"], StoreOption,
+                      "Start recording after a number of seconds");
+        ap.refer(&mut options.record_length)
+          .add_option(&["-l", "--length"], StoreOption,
+                      "Stop recording after a number of seconds");
+        ap.refer(&mut options.bell)
+          .add_option(&["--bell"], StoreTrue,
+                      "Ring the terminal bell when recording starts and stops, and during the --delayed countdown");
+        ap.refer(&mut options.notify_command)
+          .add_option(&["--notify"], StoreOption,
+                      "Shell command to run when recording starts and stops, with SWAN_AG_EVENT set to record-start or record-stop");
+        ap.refer(&mut options.record_key)
+          .add_option(&["-r", "--record-key"], Store,
+                      "Key that stops recording, with optional modifiers (default: Esc)");
+        ap.refer(&mut options.seat)
+          .add_option(&["--seat"], Store,
+                      "Seat whose devices are read (default: seat0)");
+        ap.refer(&mut options.devices)
+          .add_option(&["--device"], Collect,
+                      "Only record devices matching a name pattern like \"Logitech*\", a sysname, vendor:product, keyboard, pointer, touch or a /dev/input path. May be repeated (default: every device on the seat)");
+        ap.refer(&mut options.absolute)
+          .add_option(&["-a", "--absolute"], StoreTrue,
+                      "Record pointer positions instead of relative motion. Requires --screen");
+        ap.refer(&mut options.screen)
+          .add_option(&["--screen"], StoreOption,
+                      "Size of the screen in pixels for --absolute, like 1920x1080");
+        parse_subcommand(ap, args);
+    }
+
+    Command::Record(output)
+}
+
+fn parse_play_args(args: Vec<String>, options: &mut Options, instant: &mut bool) -> Command {
+    let mut file = String::new();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Replay a saved recording.");
+        ap.refer(&mut file)
+          .add_argument("file", Store,
+                        "Recording to replay")
+          .required();
+        ap.refer(instant)
+          .add_option(&["-i", "--instant"], StoreTrue,
+                      "Replay events with no delay between them");
+        ap.refer(&mut options.bell)
+          .add_option(&["--bell"], StoreTrue,
+                      "Ring the terminal bell when the replay starts and stops");
+        ap.refer(&mut options.notify_command)
+          .add_option(&["--notify"], StoreOption,
+                      "Shell command to run when the replay starts and stops, with SWAN_AG_EVENT set to replay-start or replay-stop");
+        ap.refer(&mut options.speed)
+          .add_option(&["-s", "--speed"], Store,
+                      "Replay speed modifier (default: 1.0)");
+        ap.refer(&mut options.lead_in)
+          .add_option(&["--lead-in"], Store,
+                      "Seconds to wait before the first event, or original for the time from the start of the recording (default: 0)");
+        ap.refer(&mut options.tail)
+          .add_option(&["--tail"], Store,
+                      "Seconds to wait after the last event, or original for the time until the end of the recording (default: 0)");
+        ap.refer(&mut options.repeat)
+          .add_option(&["--repeat"], Store,
+                      "Replay a number of times (default: 1)");
+        ap.refer(&mut options.loop_replay)
+          .add_option(&["--loop"], StoreTrue,
+                      "Replay over and over until the abort key is pressed");
+        ap.refer(&mut options.repeat_delay)
+          .add_option(&["--repeat-delay"], Store,
+                      "Seconds to wait between replays (default: 0)");
+        ap.refer(&mut options.abort_key)
+          .add_option(&["--abort-key"], Store,
+                      "Key that stops repeated replays, with optional modifiers (default: Pause)");
+        ap.refer(&mut options.panic_key)
+          .add_option(&["--panic-key"], Store,
+                      "Key that stops the replay immediately, with optional modifiers (default: ScrollLock)");
+        ap.refer(&mut options.absolute)
+          .add_option(&["-a", "--absolute"], StoreTrue,
+                      "Replay pointer positions with an absolute pointer device. Requires --screen");
+        ap.refer(&mut options.screen)
+          .add_option(&["--screen"], StoreOption,
+                      "Size of the screen in pixels for --absolute, like 1920x1080");
+        ap.refer(&mut options.device_name)
+          .add_option(&["--device-name"], Store,
+                      "Name of the virtual device events are replayed with (default: swan-ag virtual input)");
+        ap.refer(&mut options.device_id)
+          .add_option(&["--device-id"], Store,
+                      "Bus type, vendor, product and optional version of the virtual device, in hex like 0003:046d:c52b (default: 0006:0000:0000:0001)");
+        ap.refer(&mut options.device_props)
+          .add_option(&["--device-prop"], Collect,
+                      "Input property of the virtual device, like pointer or direct. May be repeated");
+        parse_subcommand(ap, args);
+    }
+
+    Command::Play(file)
+}
+
+fn parse_list_devices_args(args: Vec<String>, options: &mut Options) -> Command {
+    let mut ap = ArgumentParser::new();
+    ap.set_description("Print the input devices on the seat.");
+    ap.refer(&mut options.seat)
+      .add_option(&["--seat"], Store,
+                  "Seat whose devices are printed (default: seat0)");
+    ap.refer(&mut options.devices)
+      .add_option(&["--device"], Collect,
+                  "Only print devices matching a name pattern, sysname, vendor:product, capability or /dev/input path. May be repeated");
+    parse_subcommand(ap, args);
+
+    Command::ListDevices
+}
+
+fn parse_inspect_args(args: Vec<String>) -> Command {
+    let mut file = String::new();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Print the events in a saved recording.");
+        ap.refer(&mut file)
+          .add_argument("file", Store,
+                        "Recording to inspect")
+          .required();
+        parse_subcommand(ap, args);
+    }
+
+    Command::Inspect(file)
+}
+
+/// Parse subcommand arguments, exiting on error or after printing help.
+fn parse_subcommand(ap: ArgumentParser, args: Vec<String>) {
+    if let Err(code) = ap.parse(args, &mut stdout(), &mut stderr()) {
+        process::exit(code);
+    }
+}
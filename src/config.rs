@@ -0,0 +1,111 @@
+//! The swan-ag config file.
+//!
+//! The config file lives at `$XDG_CONFIG_HOME/swan-ag/config` (or `~/.config/swan-ag/config`) and
+//! holds one `name = value` setting per line. Blank lines and lines starting with `#` are ignored.
+//! Settings take the same values as the command line options of the same name, which override
+//! them. Settings that may be given more than once, like `slot`, are added to the command line
+//! values instead.
+//!
+//! ```text
+//! record_key = Ctrl+Alt+R
+//! replay_key = Ctrl+Alt+P
+//! slot = login=Ctrl+Alt+1
+//! ```
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+/// Settings read from the config file.
+#[derive(Default)]
+pub struct Config {
+    path: PathBuf,
+    entries: Vec<(usize, String, String)>, // Line number, name and value
+}
+
+impl Config {
+    /// Read the config file. A missing config file gives an empty config.
+    pub fn load() -> Config {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Config::default(),
+        };
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Config::default(),
+            Err(e) => {
+                eprintln!("Failed to open config file {}: {}", path.display(), e);
+                process::exit(1);
+            },
+        };
+
+        let mut entries = Vec::new();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.unwrap_or_else(|e| {
+                eprintln!("Failed to read config file {}: {}", path.display(), e);
+                process::exit(1);
+            });
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.find('=') {
+                Some(i) => {
+                    let name = line[..i].trim().to_string();
+                    let value = line[i + 1..].trim().to_string();
+                    entries.push((n + 1, name, value));
+                },
+                None => {
+                    eprintln!("{}:{}: expected name = value", path.display(), n + 1);
+                    process::exit(1);
+                },
+            }
+        }
+
+        Config {
+            path: path,
+            entries: entries,
+        }
+    }
+
+    /// Returns the last value given for a setting, exiting the program if it can't be parsed.
+    pub fn get<T>(&self, name: &str) -> Option<T>
+        where T: FromStr, T::Err: Display
+    {
+        self.entries.iter().rev().find(|e| e.1 == name).map(|e| self.parse(e))
+    }
+
+    /// Returns every value given for a setting, in file order.
+    pub fn get_all<T>(&self, name: &str) -> Vec<T>
+        where T: FromStr, T::Err: Display
+    {
+        self.entries.iter().filter(|e| e.1 == name).map(|e| self.parse(e)).collect()
+    }
+
+    /// Parse the value of an entry, exiting the program if it can't be parsed.
+    fn parse<T>(&self, entry: &(usize, String, String)) -> T
+        where T: FromStr, T::Err: Display
+    {
+        let (line, ref name, ref value) = *entry;
+
+        value.parse().unwrap_or_else(|e| {
+            eprintln!("{}:{}: bad value for {}: {}", self.path.display(), line, name, e);
+            process::exit(1);
+        })
+    }
+}
+
+/// Returns the location of the config file.
+fn config_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_home.join("swan-ag").join("config"))
+}
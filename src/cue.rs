@@ -0,0 +1,74 @@
+//! Letting the user know when recordings and replays start and stop: a countdown for --delayed,
+//! the terminal bell and a notification command.
+use abort;
+use cli::Options;
+use std::io::{self, Write};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use timer;
+
+/// A moment the user may want to be told about.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cue {
+    RecordStart,
+    RecordStop,
+    ReplayStart,
+    ReplayStop,
+}
+
+impl Cue {
+    /// Name of the cue, which the notification command gets in `SWAN_AG_EVENT`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Cue::RecordStart => "record-start",
+            Cue::RecordStop => "record-stop",
+            Cue::ReplayStart => "replay-start",
+            Cue::ReplayStop => "replay-stop",
+        }
+    }
+}
+
+/// Ring the terminal bell with --bell and run the --notify command. The command runs through `sh`
+/// in the background, so a slow one doesn't hold up recording or replaying.
+pub fn notify(options: &Options, cue: Cue) {
+    if options.bell {
+        print!("\x07");
+        io::stdout().flush().ok();
+    }
+
+    if let Some(ref command) = options.notify_command {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("SWAN_AG_EVENT", cue.name())
+            .spawn();
+        match child {
+            // Wait for it on another thread so it doesn't linger as a zombie.
+            Ok(mut child) => { thread::spawn(move || child.wait()); },
+            Err(e) => println!("Failed to run notification command: {}", e),
+        }
+    }
+}
+
+/// Count down the seconds until recording starts on stdout, ringing the bell with --bell on each
+/// second. Returns false if SIGINT or SIGTERM arrived before the countdown ended.
+pub fn countdown(options: &Options, delay: Duration) -> bool {
+    let end = timer::monotonic_now() + delay;
+    let mut remaining = delay.as_secs() + if delay.subsec_nanos() > 0 { 1 } else { 0 };
+
+    while remaining > 0 {
+        println!("Recording in {}...", remaining);
+        if options.bell {
+            print!("\x07");
+            io::stdout().flush().ok();
+        }
+
+        remaining -= 1;
+        if !abort::sleep_until_terminating(end - Duration::new(remaining, 0)) {
+            return false;
+        }
+    }
+
+    true
+}
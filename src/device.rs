@@ -0,0 +1,92 @@
+//! Choosing which input devices to record.
+use input::{Device, DeviceCapability};
+use std::str::FromStr;
+
+/// Selects input devices, written as one of:
+///
+/// * a device node like `/dev/input/event3`, opened directly instead of through the seat
+/// * a sysname like `event3`
+/// * vendor and product IDs in hex like `046d:c52b`
+/// * a capability, `keyboard`, `pointer` or `touch`
+/// * otherwise a device name, where `*` matches any text and `?` any one character
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceFilter {
+    Path(String),
+    Sysname(String),
+    Id(u32, u32),
+    Capability(DeviceCapability),
+    Name(String),
+}
+
+impl DeviceFilter {
+    pub fn matches(&self, device: &Device) -> bool {
+        match *self {
+            // libinput only opens the given paths, so every device it reports was chosen. The
+            // sysname can't be compared, as paths like /dev/input/by-id/... are symlinks.
+            DeviceFilter::Path(_) => true,
+            DeviceFilter::Sysname(ref sysname) => device.sysname() == sysname,
+            DeviceFilter::Id(vendor, product) => device.id_vendor() == vendor && device.id_product() == product,
+            DeviceFilter::Capability(capability) => device.has_capability(capability),
+            DeviceFilter::Name(ref pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let name: Vec<char> = device.name().chars().collect();
+                glob_matches(&pattern, &name)
+            },
+        }
+    }
+}
+
+impl FromStr for DeviceFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DeviceFilter, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Expected a device name, sysname, vendor:product, capability or path".to_string());
+        }
+
+        let filter = if s.starts_with('/') {
+            DeviceFilter::Path(s.to_string())
+        } else if s.len() > 5 && s.starts_with("event") && s[5..].chars().all(|c| c.is_digit(10)) {
+            DeviceFilter::Sysname(s.to_string())
+        } else if let Some((vendor, product)) = parse_id(s) {
+            DeviceFilter::Id(vendor, product)
+        } else {
+            match s {
+                "keyboard" => DeviceFilter::Capability(DeviceCapability::Keyboard),
+                "pointer" => DeviceFilter::Capability(DeviceCapability::Pointer),
+                "touch" => DeviceFilter::Capability(DeviceCapability::Touch),
+                _ => DeviceFilter::Name(s.to_string()),
+            }
+        };
+
+        Ok(filter)
+    }
+}
+
+/// Returns whether a device matches any of the filters. No filters match every device.
+pub fn any_matches(filters: &[DeviceFilter], device: &Device) -> bool {
+    filters.is_empty() || filters.iter().any(|f| f.matches(device))
+}
+
+/// Parse vendor and product IDs written like "046d:c52b".
+fn parse_id(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.split(':');
+    let vendor = parts.next()?;
+    let product = parts.next()?;
+    if parts.next().is_some() || vendor.len() != 4 || product.len() != 4 {
+        return None;
+    }
+
+    Some((u32::from_str_radix(vendor, 16).ok()?, u32::from_str_radix(product, 16).ok()?))
+}
+
+/// Match a name against a pattern where `*` matches any text and `?` any one character.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..name.len() + 1).any(|i| glob_matches(rest, &name[i..])),
+        Some((&'?', rest)) => !name.is_empty() && glob_matches(rest, &name[1..]),
+        Some((&c, rest)) => name.first() == Some(&c) && glob_matches(rest, &name[1..]),
+    }
+}
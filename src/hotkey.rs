@@ -0,0 +1,155 @@
+//! Hotkeys made of a key and any number of modifiers, written like "Ctrl+Alt+R".
+use std::fmt;
+use std::str::FromStr;
+use uinput::Key;
+
+/// A modifier that may be held on either side of the keyboard.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Super,
+}
+
+impl Modifier {
+    /// Returns the modifier the given key acts as, if any.
+    pub fn from_key(key: Key) -> Option<Modifier> {
+        match key {
+            Key::LeftCtrl | Key::RightCtrl => Some(Modifier::Ctrl),
+            Key::LeftAlt | Key::RightAlt => Some(Modifier::Alt),
+            Key::LeftShift | Key::RightShift => Some(Modifier::Shift),
+            Key::LeftMeta | Key::RightMeta => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Modifier> {
+        match name.to_lowercase().as_str() {
+            "ctrl" | "control" => Some(Modifier::Ctrl),
+            "alt" => Some(Modifier::Alt),
+            "shift" => Some(Modifier::Shift),
+            "super" | "meta" | "logo" => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+}
+
+/// A key that triggers an action when pressed with exactly the given modifiers held.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hotkey {
+    modifiers: Vec<Modifier>,
+    key: Key,
+}
+
+impl Hotkey {
+    /// A hotkey without modifiers.
+    pub fn new(key: Key) -> Hotkey {
+        Hotkey {
+            modifiers: Vec::new(),
+            key: key,
+        }
+    }
+
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// Check if an event for the given key belongs to this hotkey. The state must already be
+    /// updated with the event. A release belongs to the hotkey its press matched, even if some of
+    /// the modifiers were let go first.
+    pub fn matches(&self, key: Key, held: &ModifierState) -> bool {
+        key == self.key && held.modifiers_for(key) == self.modifiers
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Hotkey, String> {
+        let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let key_name = parts.pop().unwrap_or("");
+
+        let mut modifiers = Vec::new();
+        for name in parts {
+            match Modifier::from_name(name) {
+                Some(modifier) => modifiers.push(modifier),
+                None => return Err(format!("Unknown modifier {} in hotkey {}", name, s)),
+            }
+        }
+        modifiers.sort();
+        modifiers.dedup();
+
+        let key = match key_name.parse() {
+            Ok(key) => key,
+            Err(()) => return Err(format!("Unknown key {} in hotkey {}", key_name, s)),
+        };
+
+        Ok(Hotkey {
+            modifiers: modifiers,
+            key: key,
+        })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{:?}+", modifier)?;
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// Tracks which modifier keys are currently held down, and which modifiers were held when each
+/// pressed key went down.
+#[derive(Default)]
+pub struct ModifierState {
+    held: Vec<Key>,
+    pressed: Vec<(Key, Vec<Modifier>)>,
+    last: Option<(Key, Vec<Modifier>)>, // Last updated key and the modifiers that apply to it
+}
+
+impl ModifierState {
+    /// Update the state with a key event.
+    pub fn update(&mut self, key: Key, pressed: bool) {
+        let modifiers = match self.pressed.iter().position(|&(k, _)| k == key) {
+            Some(i) if !pressed => self.pressed.remove(i).1,
+            _ => self.active_except(key),
+        };
+        self.pressed.retain(|&(k, _)| k != key);
+        if pressed {
+            self.pressed.push((key, modifiers.clone()));
+        }
+        self.last = Some((key, modifiers));
+
+        if Modifier::from_key(key).is_none() {
+            return;
+        }
+
+        self.held.retain(|&k| k != key);
+        if pressed {
+            self.held.push(key);
+        }
+    }
+
+    /// Returns the modifiers that apply to the last event of the given key: those held when it was
+    /// pressed.
+    fn modifiers_for(&self, key: Key) -> Vec<Modifier> {
+        match self.last {
+            Some((k, ref modifiers)) if k == key => modifiers.clone(),
+            _ => self.active_except(key),
+        }
+    }
+
+    /// Returns the modifiers held by keys other than the given one, sorted and without duplicates.
+    fn active_except(&self, key: Key) -> Vec<Modifier> {
+        let mut active: Vec<Modifier> = self.held.iter()
+            .filter(|&&k| k != key)
+            .filter_map(|&k| Modifier::from_key(k))
+            .collect();
+        active.sort();
+        active.dedup();
+        active
+    }
+}
@@ -0,0 +1,151 @@
+//! Named macro slots, each bound to a replay hotkey and saved in a library directory.
+//!
+//! Every slot is stored as `<library>/<name>.swan` in the recording format, so slots survive
+//! restarts and can be replayed with `swan-ag play`.
+use hotkey::{Hotkey, ModifierState};
+use recording::{self, RecordedEvent};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use uinput::Key;
+
+/// File extension of recordings in the library.
+const EXTENSION: &'static str = "swan";
+
+/// A slot name bound to the hotkey that replays it, written like "name=Ctrl+F1".
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotBinding {
+    pub name: String,
+    pub hotkey: Hotkey,
+}
+
+impl SlotBinding {
+    /// The default bindings, slots f1 to f12 replayed with Ctrl+F1 to Ctrl+F12.
+    pub fn defaults() -> Vec<SlotBinding> {
+        (1..13).map(|n| {
+            SlotBinding {
+                name: format!("f{}", n),
+                hotkey: format!("Ctrl+F{}", n).parse().expect("invalid default slot hotkey"),
+            }
+        }).collect()
+    }
+}
+
+impl FromStr for SlotBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SlotBinding, String> {
+        let i = match s.find('=') {
+            Some(i) => i,
+            None => return Err(format!("Expected name=hotkey, got {}", s)),
+        };
+        let name = s[..i].trim();
+        let valid_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        if name.is_empty() || !name.chars().all(valid_char) {
+            return Err(format!("Slot names may only contain letters, digits, - and _, got {}", name));
+        }
+
+        Ok(SlotBinding {
+            name: name.to_string(),
+            hotkey: s[i + 1..].trim().parse()?,
+        })
+    }
+}
+
+/// A named recording.
+pub struct Slot {
+    pub name: String,
+    pub hotkey: Hotkey,
+    pub events: Vec<RecordedEvent>,
+}
+
+/// The set of slots and the one currently selected for recording and replay.
+pub struct Library {
+    dir: PathBuf,
+    slots: Vec<Slot>,
+    selected: usize,
+}
+
+impl Library {
+    /// Create the library directory if needed and load the recording of every bound slot in it.
+    /// Slots without a saved recording start out empty.
+    pub fn open(dir: PathBuf, bindings: &[SlotBinding]) -> io::Result<Library> {
+        assert!(!bindings.is_empty(), "a library needs at least one slot");
+        fs::create_dir_all(&dir)?;
+
+        let mut slots = Vec::new();
+        for binding in bindings {
+            let path = dir.join(&binding.name).with_extension(EXTENSION);
+            let events = if path.exists() {
+                recording::load(&path)?
+            } else {
+                Vec::new()
+            };
+
+            slots.push(Slot {
+                name: binding.name.clone(),
+                hotkey: binding.hotkey.clone(),
+                events: events,
+            });
+        }
+
+        Ok(Library {
+            dir: dir,
+            slots: slots,
+            selected: 0,
+        })
+    }
+
+    pub fn slot(&self, index: usize) -> &Slot {
+        &self.slots[index]
+    }
+
+    /// Returns the index of the slot with the given name.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|s| s.name == name)
+    }
+
+    /// Returns the index of the slot whose hotkey an event for the given key belongs to.
+    pub fn find_hotkey(&self, key: Key, held: &ModifierState) -> Option<usize> {
+        self.slots.iter().position(|s| s.hotkey.matches(key, held))
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: usize) {
+        assert!(index < self.slots.len());
+        self.selected = index;
+    }
+
+    /// Replace the events of a slot without saving them.
+    pub fn set(&mut self, index: usize, events: Vec<RecordedEvent>) {
+        self.slots[index].events = events;
+    }
+
+    /// Replace the events of a slot and save them to the library directory.
+    pub fn store(&mut self, index: usize, events: Vec<RecordedEvent>) -> io::Result<PathBuf> {
+        let path = self.dir.join(&self.slots[index].name).with_extension(EXTENSION);
+
+        recording::save(&path, &events)?;
+        self.slots[index].events = events;
+
+        Ok(path)
+    }
+}
+
+/// Returns the default library directory, `$XDG_DATA_HOME/swan-ag/macros`.
+pub fn default_dir() -> PathBuf {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME").unwrap_or_default();
+            PathBuf::from(home).join(".local").join("share")
+        },
+    };
+
+    data_home.join("swan-ag").join("macros")
+}
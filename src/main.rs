@@ -3,24 +3,38 @@ extern crate input;
 extern crate libc;
 extern crate libudev_sys;
 #[macro_use] extern crate nix;
-extern crate time;
 
+mod abort;
+mod cli;
+mod config;
+mod cue;
+mod device;
+mod hotkey;
+mod library;
+mod pointer;
+mod recorder;
+mod recording;
+mod replay;
+mod timer;
 mod uinput;
 
-use argparse::{ArgumentParser, Store, StoreOption, StoreTrue};
-use input::{AsRaw, Libinput, LibinputInterface};
-use input::Event::{Keyboard, Pointer};
-use input::event::Event;
+use cli::{Command, Options};
+use cue::Cue;
+use device::DeviceFilter;
+use hotkey::{Hotkey, ModifierState};
+use library::Library;
+use input::{AsRaw, DeviceCapability, Libinput, LibinputInterface};
+use input::Event::{Device, Keyboard};
+use input::event::{DeviceEvent, Event, EventTrait};
 use input::event::KeyboardEvent::Key;
-use input::event::PointerEvent::{Button, Motion};
 use input::event::keyboard::{KeyboardEventTrait, KeyState};
-use input::event::pointer::PointerEventTrait;
 use libc::{c_char, c_int, c_void};
-use uinput::UInput;
+use recorder::Recorder;
+use recording::RecordedEvent;
+use std::os::unix::io::AsRawFd;
+use timer::Timer;
+use uinput::{UInput, UInputBuilder};
 
-const SEAT_NAME: &'static str = "seat0";
-const RECORD_KEY: uinput::Key = uinput::Key::Esc;
-const REPLAY_KEY: uinput::Key = uinput::Key::F2;
 static INTERFACE: LibinputInterface = LibinputInterface {
     open_restricted: Some(open_restricted),
     close_restricted: Some(close_restricted),
@@ -43,25 +57,8 @@ extern fn close_restricted(fd: c_int, user_data: *mut c_void) {
     }
 }
 
-/// Command line options for this program.
-struct Options {
-    speed: f64,
-    record_delay: Option<f64>,
-    record_length: Option<f64>
-}
-
-impl Default for Options {
-    fn default() -> Self {
-        Options {
-            speed: 1.0,
-            record_delay: None,
-            record_length: None,
-        }
-    }
-}
-
-/// Create a Libinput struct from udev
-unsafe fn libinput_from_udev() -> Libinput {
+/// Create a Libinput struct from udev for the devices on the given seat.
+unsafe fn libinput_from_udev(seat: &str) -> Result<Libinput, String> {
     let udev = libudev_sys::udev_new();
     if udev.is_null() {
         panic!("Could not create udev context.");
@@ -72,246 +69,625 @@ unsafe fn libinput_from_udev() -> Libinput {
         panic!("Failed to create libinput context.");
     }
 
-    libinput.udev_assign_seat(SEAT_NAME).ok();
-
+    let assigned = libinput.udev_assign_seat(seat);
     libudev_sys::udev_unref(udev);
+    if assigned.is_err() {
+        return Err(format!("Failed to assign seat {}. Check that it exists and that you can read its input devices.", seat));
+    }
 
-    libinput
+    Ok(libinput)
 }
 
-/// Replay events in the event store.
-/// Modifies the pointer position.
-fn replay_events(options: &Options, events: &Vec<Event>, uinput: &mut UInput) {
-    println!("Replay!");
-    let mut prev_event_time = 0;
-    let mut pointer_err = (0_f64, 0_f64); // Total accumulated positional error
-
-    for e in events {
-        // Not ideal, but can't get time on generic Event (Devices don't have a time)
-        // Each event should assign this value to msec time
-        let mut time = prev_event_time;
-
-        match e {
-            &Keyboard(Key(ref key_event)) => {
-                let key = key_event.key();
-                time = key_event.time_usec() / 1000;
-
-                match key_event.key_state() {
-                    KeyState::Pressed => {
-                        uinput.key_press(uinput::Key::from(key as u8));
-                    },
-                    KeyState::Released => {
-                        uinput.key_release(uinput::Key::from(key as u8));
-                    },
-                }
-            },
-            &Pointer(Motion(ref motion_event)) => {
-                // This assumes that the units from libinput are the same as that of the uinput
-                // device. This is WRONG and doesn't work for some devices. (i.e. my touchpad)
-                // Using accelerated data makes touchpads work slightly better but makes worse
-                // mouse control.
-                let x = motion_event.dx_unaccelerated();
-                let y = motion_event.dy_unaccelerated();
-                //println!("Rel {} {}", x, y);
-                time = motion_event.time_usec() / 1000;
-
-                uinput.rel_x(x as i32);
-                uinput.rel_y(y as i32);
-
-                // Though unaccelerated data is typically integers.
-                pointer_err.0 += x.fract();
-                pointer_err.1 += y.fract();
-
-                if pointer_err.0.abs() > 1.0 {
-                    uinput.rel_x(pointer_err.0 as i32);      // Sends 1 or -1
-                    pointer_err.0 -= pointer_err.0.trunc(); // Subtracts 1 or -1.
-                }
-                if pointer_err.1.abs() > 1.0 {
-                    uinput.rel_y(pointer_err.1 as i32);             // Sends 1 or -1
-                    pointer_err.1 -= pointer_err.1.trunc(); // Subtracts 1 or -1.
-                }
-            },
-            &Pointer(Button(ref button_event)) => {
-                let button = button_event.button();
-                let value = button_event.seat_button_count();
-
-                match (button, value) {
-                    (0x110, 0) => uinput.btn_left_release(),
-                    (0x110, 1) => uinput.btn_left_press(),
-                    (0x111, 0) => uinput.btn_right_release(),
-                    (0x111, 1) => uinput.btn_right_press(),
-                    _ => println!("Unimplemented button event!")
+/// Create a Libinput struct that only reads the given device nodes.
+fn libinput_from_paths(paths: &[&str]) -> Result<Libinput, String> {
+    let mut libinput = Libinput::new_from_path::<&str>(INTERFACE, None);
+    if libinput.as_raw().is_null() {
+        panic!("Failed to create libinput context.");
+    }
+
+    for path in paths {
+        if libinput.path_add_device(path).is_none() {
+            return Err(format!("Failed to open input device {}.", path));
+        }
+    }
+
+    Ok(libinput)
+}
+
+/// Create a Libinput struct for the device nodes given with --device, or for the whole --seat if
+/// there are none.
+fn try_open_libinput(options: &Options) -> Result<Libinput, String> {
+    let paths: Vec<&str> = options.devices.iter().filter_map(|d| match *d {
+        DeviceFilter::Path(ref path) => Some(path.as_str()),
+        _ => None,
+    }).collect();
+
+    if paths.is_empty() {
+        unsafe { libinput_from_udev(&options.seat) }
+    } else {
+        libinput_from_paths(&paths)
+    }
+}
+
+/// Like try_open_libinput, but exits the program if libinput can't be opened.
+fn open_libinput(options: &Options) -> Libinput {
+    try_open_libinput(options).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Count down the --delayed seconds, if any. Returns false if interrupted by SIGINT or SIGTERM.
+fn wait_record_delay(options: &Options) -> bool {
+    match options.record_delay {
+        Some(duration) => cue::countdown(options, timer::from_secs(duration)),
+        None => true,
+    }
+}
+
+/// Returns the time on the monotonic clock a number of seconds in floating point after the given
+/// time.
+fn deadline_after(start_time: std::time::Duration, duration: f64) -> std::time::Duration {
+    start_time + timer::from_secs(duration)
+}
+
+/// Check if a number of seconds in floating point have passed between the given start time and
+/// now on the monotonic clock.
+fn time_has_elapsed(start_time: std::time::Duration, duration: f64, now: std::time::Duration) -> bool {
+    now >= deadline_after(start_time, duration)
+}
+
+/// Create the uinput device replayed events are written to: a keyboard and mouse, whose pointer
+/// moves to positions on the screen in --absolute mode. Exits the program if it can't be created.
+fn create_uinput(options: &Options) -> UInput {
+    let mut builder = UInputBuilder::new()
+        .name(&options.device_name)
+        .id(options.device_id)
+        .keys(1..150) // Most of the keyboard keys.
+        .keys(uinput::BTN_LEFT..uinput::BTN_TASK + 1)
+        .rel(uinput::REL_WHEEL)
+        .rel(uinput::REL_HWHEEL)
+        .rel(uinput::REL_WHEEL_HI_RES)
+        .rel(uinput::REL_HWHEEL_HI_RES);
+
+    builder = match options.screen {
+        Some(screen) if options.absolute => {
+            builder.abs(uinput::ABS_X, 0, screen.width as i32 - 1)
+                   .abs(uinput::ABS_Y, 0, screen.height as i32 - 1)
+        },
+        _ => builder.rel(uinput::REL_X).rel(uinput::REL_Y),
+    };
+    for &prop in &options.device_props {
+        builder = builder.prop(prop);
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("Failed to create uinput device: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Returns whether an event came from swan-ag's own uinput device, so replayed events are neither
+/// recorded nor taken for hotkeys. Without a known event node the device is matched by name.
+fn is_own_event(event: &Event, uinput: &UInput) -> bool {
+    let device = event.device();
+    match uinput.devnode().and_then(|path| path.file_name()) {
+        Some(node) => node.to_str() == Some(device.sysname()),
+        None => device.name() == uinput.name(),
+    }
+}
+
+/// Returns the event nodes of the devices libinput opened, apart from swan-ag's own, from the
+/// device-added events it queues when opened. Input that arrives meanwhile is dropped, as nothing
+/// is being recorded yet.
+///
+/// In --absolute mode this also waits up to a second for libinput to report swan-ag's own device.
+/// The compositor picks new devices up at about the same time, and pointer moves sent to the
+/// device before then are lost. libinput reading only the --device paths never reports the device,
+/// so there's nothing to wait for then.
+fn read_added_devices(options: &Options, libinput: &mut Libinput, uinput: &UInput, timer: &Timer)
+    -> Vec<std::path::PathBuf>
+{
+    let paths_only = options.devices.iter().any(|d| match *d { DeviceFilter::Path(_) => true, _ => false });
+    let mut waiting = options.absolute && !paths_only;
+    let deadline = timer::monotonic_now() + std::time::Duration::new(1, 0);
+    timer.set_deadline(if waiting { Some(deadline) } else { None }).expect("Failed to arm the device timer.");
+
+    if let Err(_) = libinput.dispatch() {
+        panic!("libinput dispatch failed.");
+    }
+    let mut devices = Vec::new();
+    loop {
+        while let Some(event) = libinput.next() {
+            if let Device(DeviceEvent::Added(ref added)) = event {
+                if is_own_event(&event, uinput) {
+                    waiting = false;
+                } else {
+                    devices.push(std::path::Path::new("/dev/input").join(added.device().sysname()));
                 }
-            },
-            _ => {},
+            }
         }
-        // Sleep for event delta time then send event
-        // Sometimes events become unordered and time is off.
-        if options.speed != 0.0 && prev_event_time != 0 && prev_event_time < time {
-            let delay_ms = (time - prev_event_time) as f64 / options.speed;
-            std::thread::sleep(std::time::Duration::from_millis(delay_ms as u64));
+
+        if !waiting || timer::monotonic_now() >= deadline || abort::terminating() {
+            break;
         }
-        prev_event_time = time;
+        wait_for_events(libinput, timer);
+    }
 
-        // For some events like motion, this is not necessary.
-        uinput.sync();
+    if waiting && !abort::terminating() {
+        println!("libinput didn't report the virtual device, so the pointer may not start in the top left corner.");
     }
+    timer.set_deadline(None).expect("Failed to disarm the device timer.");
+    devices
 }
 
-/// Return the options struct based on command line arguments.
-fn parse_args() -> Options {
-    let mut options = Options::default();
-    let mut instant = false;
-    {
-        let mut ap = ArgumentParser::new();
-        ap.set_description("Record input events and replay them. Use the --delayed and --length option for buttonless recording.");
-        ap.refer(&mut options.record_delay)
-          .add_option(&["-d", "--delayed"], StoreOption,
-                      "Start recording after a number of seconds");
-        ap.refer(&mut instant)
-          .add_option(&["-i", "--instant"], StoreTrue,
-                      "Replay events with no delay between them");
-        ap.refer(&mut options.record_length)
-          .add_option(&["-l", "--length"], StoreOption,
-                      "Recordings will stop after a number of seconds");
-        /*
-        ap.refer(&mut options.no_flush)
-          .add_option(&["-n", "--no-flush"], StoreTrue,
-                      "Don't flush stored events between recordings");
-        ap.refer(&mut options.record_key)
-          .add_option(&["-r", "--record-key"], Store,
-                      "User specified replay key");
-        ap.refer(&mut options.no_flush)
-          .add_option(&["-p", "--replay-key"], Store,
-                      "User specified record key");
-                      */
-        ap.refer(&mut options.speed)
-          .add_option(&["-s", "--speed"], Store,
-                      "Replay speed modifier (default: 1.0)");
-        ap.parse_args_or_exit();
-    }
-
-    if instant {
-        options.speed = 0.0;
-    }
-
-    options
+/// Start recording and arm the timer to wake the event loop when --length runs out. In --absolute
+/// mode the pointer is first moved to the top left corner, where position tracking starts, and
+/// recording doesn't start if it can't be moved.
+fn start_recording(options: &Options, recorder: &mut Recorder, uinput: Option<&mut UInput>, timer: &Timer)
+    -> uinput::Result<()>
+{
+    if options.absolute {
+        if let Some(uinput) = uinput {
+            // The kernel drops values equal to the axis's current one, and new axes start at 0,
+            // so move away from the corner first.
+            uinput.abs_x(1)?;
+            uinput.abs_y(1)?;
+            uinput.sync()?;
+            uinput.abs_x(0)?;
+            uinput.abs_y(0)?;
+            uinput.sync()?;
+        }
+        recorder.start(options.screen);
+    } else {
+        recorder.start(None);
+    }
+    cue::notify(options, Cue::RecordStart);
+
+    // The timer expires exactly when time_has_elapsed becomes true.
+    let deadline = options.record_length.map(|duration| deadline_after(recorder.start_time(), duration));
+    timer.set_deadline(deadline).expect("Failed to arm the --length timer.");
+
+    Ok(())
 }
 
-/// Returns the secs and nsecs of the given floating point seconds.
-fn f64_sec(duration: f64) -> (u64, u32) {
-    if !duration.is_finite() || duration.is_sign_negative() {
-        panic!("Invalid delay value: {}", duration);
+/// Stop recording, moving the recorded events into the given slot and selecting it. The modifiers of
+/// the hotkey that stopped the recording, if any, are left out of it.
+fn stop_recording(options: &Options, recorder: &mut Recorder, library: &mut Library, slot: usize, hotkey: Option<&Hotkey>) {
+    let events = recorder.stop(hotkey);
+    cue::notify(options, Cue::RecordStop);
+
+    if let Some(ref path) = options.output_file {
+        match recording::save(path, &events) {
+            Ok(()) => println!("Saved recording to {}.", path),
+            Err(e) => println!("Failed to save recording to {}: {}", path, e),
+        }
     }
 
-    let secs = duration as u64;
-    let nsecs = (duration.fract() * 1000000000.0) as u32;
+    let name = library.slot(slot).name.clone();
+    match library.store(slot, events) {
+        Ok(path) => println!("Saved slot {} to {}.", name, path.display()),
+        Err(e) => println!("Failed to save slot {}: {}", name, e),
+    }
+    library.select(slot);
+}
 
-    (secs, nsecs)
+/// Block until there is input or the timer expires, then let libinput process pending input.
+fn wait_for_events(libinput: &mut Libinput, timer: &Timer) {
+    let mut fds = [
+        libc::pollfd { fd: libinput.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: timer.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+    ];
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+        let err = std::io::Error::last_os_error();
+        // A signal woke us up early, which the caller can handle like any other wakeup.
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            panic!("poll failed: {}", err);
+        }
+    }
+    timer.clear();
+
+    if let Err(_) = libinput.dispatch() {
+        panic!("libinput dispatch failed.");
+    }
 }
 
-/// Sleep for number of seconds in floating point
-fn sleep_secs(duration: f64) {
-    let (secs, nsecs) = f64_sec(duration);
+/// Watch for the panic key, which stops replays right away, on the given devices, or on every
+/// device but swan-ag's own if libinput couldn't be opened to tell which. Replays can still be
+/// stopped between repeats with the abort key if the panic key can't be watched.
+fn watch_panic_key(options: &Options, uinput: &UInput, devices: Option<Vec<std::path::PathBuf>>) {
+    let devices = match devices {
+        Some(devices) => Ok(devices),
+        None => abort::all_devices(uinput),
+    };
+    if let Err(e) = devices.and_then(|devices| abort::watch_panic_key(options.panic_key.clone(), &devices)) {
+        println!("Can't watch for the panic key: {}", e);
+    }
+}
 
-    let duration = std::time::Duration::new(secs, nsecs as u32);
+/// Create the timer that wakes the event loop, exiting the program if it can't be created.
+fn create_timer() -> Timer {
+    Timer::new().unwrap_or_else(|e| {
+        eprintln!("Failed to create timer: {}", e);
+        std::process::exit(1);
+    })
+}
 
-    std::thread::sleep(duration);
+/// Load a recording, exiting the program if it can't be read.
+fn load_or_exit(path: &str) -> Vec<RecordedEvent> {
+    recording::load(path).unwrap_or_else(|e| {
+        eprintln!("Failed to load recording from {}: {}", path, e);
+        std::process::exit(1);
+    })
 }
 
-/// Check if time since given Timespec is larger than given number of seconds in floating point
-fn time_has_elapsed(start_time: time::Timespec, duration: f64) -> bool {
-    let (secs, nsecs) = f64_sec(duration);
+/// Record until --length seconds pass or the record key is pressed, then save to the given path.
+fn record(options: &Options, path: &str) {
+    let mut libinput = open_libinput(options);
+    let mut uinput = if options.absolute { Some(create_uinput(options)) } else { None };
+    let mut recorder = Recorder::new(options.devices.clone());
+    let mut modifiers = ModifierState::default();
+    let timer = create_timer();
+    if let Some(ref uinput) = uinput {
+        read_added_devices(options, &mut libinput, uinput, &timer);
+    }
+
+    if !wait_record_delay(options) {
+        return;
+    }
+    if let Err(e) = start_recording(options, &mut recorder, uinput.as_mut(), &timer) {
+        eprintln!("Failed to start recording: {}", e);
+        std::process::exit(1);
+    }
 
-    // duration is in floating point seconds
-    let time_elapsed = (time::get_time() - start_time).to_std().expect("duration out of range.");
+    let mut stop_key = None;
+    'record: loop {
+        wait_for_events(&mut libinput, &timer);
+
+        // Keep what was recorded so far.
+        if abort::terminating() {
+            break;
+        }
+
+        if let Some(duration) = options.record_length {
+            if time_has_elapsed(recorder.start_time(), duration, timer::monotonic_now()) {
+                break;
+            }
+        }
 
-    time_elapsed.as_secs() > secs && time_elapsed.subsec_nanos() > nsecs
+        while let Some(event) = libinput.next() {
+            // Moving the pointer to the top left corner in --absolute mode isn't part of the recording.
+            if uinput.as_ref().map_or(false, |uinput| is_own_event(&event, uinput)) {
+                continue;
+            }
+
+            if let Keyboard(Key(ref key_event)) = event {
+                // Keys without a name can't be part of a hotkey.
+                let key = match uinput::Key::from_code(key_event.key() as u16) {
+                    Ok(key) => key,
+                    Err(_) => {
+                        recorder.capture(&event);
+                        continue;
+                    },
+                };
+                let pressed = key_event.key_state() == KeyState::Pressed;
+                modifiers.update(key, pressed);
+
+                if options.record_key.matches(key, &modifiers) {
+                    if !pressed {
+                        stop_key = Some(&options.record_key);
+                        break 'record;
+                    }
+                    continue;
+                }
+            }
+
+            recorder.capture(&event);
+        }
+    }
+    let event_store = recorder.stop(stop_key);
+    cue::notify(options, Cue::RecordStop);
+
+    if let Err(e) = recording::save(path, &event_store) {
+        eprintln!("Failed to save recording to {}: {}", path, e);
+        std::process::exit(1);
+    }
+    println!("Saved {} events to {}.", event_store.len(), path);
 }
 
-fn start_recording(event_store: &mut Vec<Event>, recording: &mut bool, start_time: &mut time::Timespec) {
-    event_store.clear();
-    *start_time = time::get_time();
-    *recording = true;
-    println!("Started recording!");
+/// Replay the recording at the given path.
+fn play(options: &Options, path: &str) {
+    let event_store = load_or_exit(path);
+    let mut uinput = create_uinput(options);
+    let timer = create_timer();
+    // Input is only read for the abort key and to tell which devices to watch for the panic key.
+    // Replaying doesn't need it, so if it can't be read the replays go on regardless.
+    let mut libinput = match try_open_libinput(options) {
+        Ok(libinput) => Some(libinput),
+        Err(e) => {
+            if options.loop_replay || options.repeat > 1 {
+                println!("{} The abort key won't work, use {} to stop the replays.", e, options.panic_key);
+            }
+            None
+        },
+    };
+    let devices = libinput.as_mut().map(|libinput| read_added_devices(options, libinput, &uinput, &timer));
+    watch_panic_key(options, &uinput, devices);
+    let mut recorder = Recorder::new(Vec::new());
+    let mut modifiers = ModifierState::default();
+
+    let result = replay::replay_repeated(options, &event_store, &mut uinput, |uinput| {
+        libinput.as_mut().map_or(false, |libinput| {
+            abort_pressed(options, libinput, None, &mut recorder, &mut modifiers, uinput)
+        })
+    });
+    if let Err(e) = result {
+        eprintln!("Replay failed: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn stop_recording(recording: &mut bool) {
-    *recording = false;
-    println!("Stopped recording!");
+/// Print the events of the recording at the given path, with times relative to the first event.
+fn inspect(path: &str) {
+    let event_store = load_or_exit(path);
+    let start = event_store.first().map_or(0, |e| e.time_usec());
+    let end = event_store.last().map_or(0, |e| e.time_usec());
+
+    println!("{}: {} events over {:.3}s", path, event_store.len(), end.saturating_sub(start) as f64 / 1e6);
+    for e in &event_store {
+        let time = e.time_usec().saturating_sub(start) as f64 / 1e6;
+        match *e {
+            RecordedEvent::Key { code, pressed, .. } => {
+                println!("{:10.6} key      {:3} {}", time, code, if pressed { "pressed" } else { "released" })
+            },
+            RecordedEvent::Button { code, pressed, .. } => {
+                println!("{:10.6} button   {:#x} {}", time, code, if pressed { "pressed" } else { "released" })
+            },
+            RecordedEvent::Motion { dx, dy, .. } => println!("{:10.6} motion   {} {}", time, dx, dy),
+            RecordedEvent::Scroll { vertical, horizontal, .. } => {
+                println!("{:10.6} scroll   {} {}", time, vertical, horizontal)
+            },
+            RecordedEvent::Absolute { x, y, .. } => println!("{:10.6} absolute {} {}", time, x, y),
+            RecordedEvent::Start { .. } => println!("{:10.6} start", time),
+            RecordedEvent::Stop { .. } => println!("{:10.6} stop", time),
+        }
+    }
 }
 
-fn main() {
-    let mut libinput = unsafe { libinput_from_udev() };
-    let mut uinput = uinput::UInput::new();
-    let mut event_store = Vec::new();
+/// Print the sysname, vendor and product IDs, capabilities and name of every device on the seat
+/// that matches --device.
+fn list_devices(options: &Options) {
+    let mut libinput = open_libinput(options);
+    if let Err(_) = libinput.dispatch() {
+        panic!("libinput dispatch failed.");
+    }
+
+    while let Some(event) = libinput.next() {
+        if let Device(DeviceEvent::Added(added)) = event {
+            let device = added.device();
+            if !device::any_matches(&options.devices, &device) {
+                continue;
+            }
+            let mut capabilities = Vec::new();
+            if device.has_capability(DeviceCapability::Keyboard) {
+                capabilities.push("keyboard");
+            }
+            if device.has_capability(DeviceCapability::Pointer) {
+                capabilities.push("pointer");
+            }
+            if device.has_capability(DeviceCapability::Touch) {
+                capabilities.push("touch");
+            }
+
+            println!("{}\t{:04x}:{:04x}\t{}\t{}", device.sysname(), device.id_vendor(), device.id_product(),
+                     capabilities.join(","), device.name());
+        }
+    }
+}
+
+/// Process the input that arrived during a replay and return whether the abort key was pressed.
+/// Replays call this while they wait, so the input is read before the kernel's buffers overflow.
+/// The input is recorded as usual, except for hotkeys, which are ignored until the replay ends.
+fn abort_pressed(options: &Options, libinput: &mut Libinput, library: Option<&Library>, recorder: &mut Recorder,
+                 modifiers: &mut ModifierState, uinput: &UInput) -> bool {
+    if let Err(_) = libinput.dispatch() {
+        panic!("libinput dispatch failed.");
+    }
+
+    let mut abort = false;
+    while let Some(event) = libinput.next() {
+        if is_own_event(&event, uinput) {
+            continue;
+        }
+
+        if let Keyboard(Key(ref key_event)) = event {
+            if let Ok(key) = uinput::Key::from_code(key_event.key() as u16) {
+                let pressed = key_event.key_state() == KeyState::Pressed;
+                modifiers.update(key, pressed);
+
+                if options.abort_key.matches(key, modifiers) {
+                    abort = abort || pressed;
+                    continue;
+                }
+                if options.record_key.matches(key, modifiers) || options.replay_key.matches(key, modifiers) ||
+                   options.panic_key.matches(key, modifiers) || library.map_or(false, |l| l.find_hotkey(key, modifiers).is_some()) {
+                    continue;
+                }
+            }
+        }
 
-    let options = parse_args();
+        recorder.capture(&event);
+    }
 
-    let mut recording = false;
-    let mut record_start_time = time::Timespec::new(0, 0);
+    abort
+}
 
-    println!("Swan-ag ready! Use ESC to record and F2 to replay.");
+/// Replay a slot, repeating it as asked. The replayed events are ignored when libinput reports
+/// them, so a recording can go on during the replay.
+fn replay_slot(options: &Options, libinput: &mut Libinput, library: &Library, slot: usize, uinput: &mut UInput,
+               recorder: &mut Recorder, modifiers: &mut ModifierState) {
+    println!("Replaying slot {}.", library.slot(slot).name);
+
+    let result = replay::replay_repeated(options, &library.slot(slot).events, uinput, |uinput| {
+        abort_pressed(options, libinput, Some(library), recorder, modifiers, uinput)
+    });
+    if let Err(e) = result {
+        println!("Replay failed: {}", e);
+    }
+}
 
-    if let Some(duration) = options.record_delay {
-        sleep_secs(duration);
-        start_recording(&mut event_store, &mut recording, &mut record_start_time);
+/// Record with the record key and replay slots with their hotkeys until interrupted.
+fn interactive(options: &Options) {
+    let mut libinput = open_libinput(options);
+    let mut uinput = create_uinput(options);
+    let timer = create_timer();
+    let devices = read_added_devices(options, &mut libinput, &uinput, &timer);
+    watch_panic_key(options, &uinput, Some(devices));
+
+    let library_dir = options.library.as_ref().map_or_else(library::default_dir, std::path::PathBuf::from);
+    let mut library = Library::open(library_dir, &options.slots).unwrap_or_else(|e| {
+        eprintln!("Failed to open slot library: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(ref name) = options.select {
+        match library.find(name) {
+            Some(slot) => library.select(slot),
+            None => {
+                eprintln!("No slot named {}.", name);
+                std::process::exit(1);
+            },
+        }
+    }
+    if let Some(ref path) = options.input_file {
+        let selected = library.selected();
+        library.set(selected, load_or_exit(path));
     }
 
-    loop {
-        if let Err(_) = libinput.dispatch() {
-            panic!("libinput dispatch failed.");
+    let mut recorder = Recorder::new(options.devices.clone());
+    let mut modifiers = ModifierState::default();
+
+    println!("Swan-ag ready! Use {} to record and {} to replay.", options.record_key, options.replay_key);
+    for slot in &options.slots {
+        println!("  {} replays slot {}", slot.hotkey, slot.name);
+    }
+    if options.loop_replay || options.repeat > 1 {
+        println!("  {} stops repeated replays", options.abort_key);
+    }
+    println!("  {} stops a replay immediately", options.panic_key);
+    println!("Slot {} is selected.", library.slot(library.selected()).name);
+
+    if options.record_delay.is_some() && wait_record_delay(options) {
+        if let Err(e) = start_recording(options, &mut recorder, Some(&mut uinput), &timer) {
+            println!("Failed to start recording: {}", e);
         }
-        // This dispatch doesn't block and causes a busy loop. For now lets just sleep.
-        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    while !abort::terminating() {
+        wait_for_events(&mut libinput, &timer);
 
         // Multiple events may be processed before another time check
         if let Some(duration) = options.record_length {
-            if recording && time_has_elapsed(record_start_time, duration) {
-                stop_recording(&mut recording);
+            if recorder.is_recording() && time_has_elapsed(recorder.start_time(), duration, timer::monotonic_now()) {
+                let selected = library.selected();
+                stop_recording(options, &mut recorder, &mut library, selected, None);
             }
         }
 
         while let Some(event) = libinput.next() {
+            if is_own_event(&event, &uinput) {
+                continue;
+            }
+
             match event {
                 Keyboard(Key(key_event)) => {
-                    let key = key_event.key();
-                    let key_state = key_event.key_state();
-
-                    match uinput::Key::from(key as u8) {
-                        RECORD_KEY => {
-                            if key_state == KeyState::Released {
-                                if recording {
-                                    stop_recording(&mut recording);
-                                } else {
-                                    start_recording(&mut event_store, &mut recording, &mut record_start_time);
-                                }
-                            }
+                    // Keys without a name can't be part of a hotkey.
+                    let key = match uinput::Key::from_code(key_event.key() as u16) {
+                        Ok(key) => key,
+                        Err(_) => {
+                            recorder.capture(&Keyboard(Key(key_event)));
+                            continue;
                         },
-                        REPLAY_KEY => {
-                            if key_state == KeyState::Released {
-                                if recording {
-                                    stop_recording(&mut recording);
-                                }
-
-                                libinput.suspend();
-                                replay_events(&options, &event_store, &mut uinput);
-                                if libinput.resume().is_err() {
-                                    panic!("Failed to resume libinput");
-                                }
+                    };
+                    let pressed = key_event.key_state() == KeyState::Pressed;
+                    modifiers.update(key, pressed);
+
+                    if options.record_key.matches(key, &modifiers) {
+                        if !pressed {
+                            if recorder.is_recording() {
+                                let selected = library.selected();
+                                stop_recording(options, &mut recorder, &mut library, selected, Some(&options.record_key));
+                            } else if let Err(e) = start_recording(options, &mut recorder, Some(&mut uinput), &timer) {
+                                println!("Failed to start recording: {}", e);
                             }
-                        },
-                        _ => if recording {
-                            event_store.push(Keyboard(Key(key_event)));
-                        },
-                    }
-                },
-                e => {
-                    if recording {
-                        event_store.push(e);
+                        }
+                    } else if options.replay_key.matches(key, &modifiers) {
+                        // Recording goes on during the replay, so new input can be layered over it.
+                        if !pressed {
+                            let selected = library.selected();
+                            replay_slot(options, &mut libinput, &library, selected, &mut uinput, &mut recorder, &mut modifiers);
+                        }
+                    } else if options.abort_key.matches(key, &modifiers) || options.panic_key.matches(key, &modifiers) {
+                        // These only stop replays, so there's nothing to stop here.
+                    } else if let Some(slot) = library.find_hotkey(key, &modifiers) {
+                        if !pressed {
+                            // A slot hotkey ends a recording by storing it in that slot.
+                            if recorder.is_recording() {
+                                let hotkey = library.slot(slot).hotkey.clone();
+                                stop_recording(options, &mut recorder, &mut library, slot, Some(&hotkey));
+                            } else {
+                                library.select(slot);
+                                replay_slot(options, &mut libinput, &library, slot, &mut uinput, &mut recorder, &mut modifiers);
+                            }
+                        }
+                    } else {
+                        recorder.capture(&Keyboard(Key(key_event)));
                     }
                 },
+                e => recorder.capture(&e),
             }
         }
     }
 }
+
+fn main() {
+    let options = cli::parse_args();
+    abort::handle_termination_signals();
+
+    match options.command {
+        Command::Interactive => interactive(&options),
+        Command::Record(ref path) => record(&options, path),
+        Command::Play(ref path) => play(&options, path),
+        Command::Inspect(ref path) => inspect(path),
+        Command::ListDevices => list_devices(&options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn deadline_is_start_plus_length() {
+        assert_eq!(deadline_after(Duration::new(10, 0), 2.5), Duration::new(12, 500_000_000));
+        assert_eq!(deadline_after(Duration::new(10, 600_000_000), 2.5), Duration::new(13, 100_000_000));
+        assert_eq!(deadline_after(Duration::new(10, 0), 2.0), Duration::new(12, 0));
+    }
+
+    #[test]
+    fn fractional_length_elapses() {
+        let start = Duration::new(100, 0);
+        assert!(!time_has_elapsed(start, 2.5, Duration::new(102, 0)));
+        assert!(!time_has_elapsed(start, 2.5, Duration::new(102, 499_999_999)));
+        assert!(time_has_elapsed(start, 2.5, Duration::new(102, 500_000_000)));
+        assert!(time_has_elapsed(start, 2.5, Duration::new(103, 0)));
+    }
+
+    #[test]
+    fn whole_second_length_elapses() {
+        let start = Duration::new(100, 250_000_000);
+        assert!(!time_has_elapsed(start, 2.0, Duration::new(102, 249_999_999)));
+        assert!(time_has_elapsed(start, 2.0, Duration::new(102, 250_000_000)));
+        assert!(time_has_elapsed(start, 2.0, Duration::new(105, 0)));
+    }
+}
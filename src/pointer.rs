@@ -0,0 +1,82 @@
+//! Following the pointer across the screen for absolute position recording.
+use input::Event::Pointer;
+use input::event::Event;
+use input::event::PointerEvent::{Motion, MotionAbsolute};
+use input::event::pointer::PointerEventTrait;
+use recording::RecordedEvent;
+use std::str::FromStr;
+
+/// Size of the screen in pixels, written like "1920x1080".
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScreenSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for ScreenSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ScreenSize, String> {
+        let mut parts = s.split('x');
+        let width = parts.next().and_then(|w| w.trim().parse().ok());
+        let height = parts.next().and_then(|h| h.trim().parse().ok());
+
+        match (width, height, parts.next()) {
+            (Some(width), Some(height), None) if width > 0 && height > 0 => {
+                Ok(ScreenSize {
+                    width: width,
+                    height: height,
+                })
+            },
+            _ => Err(format!("Expected a screen size like 1920x1080, got {}", s)),
+        }
+    }
+}
+
+/// Tracks the pointer position so relative motion can be recorded as absolute positions.
+///
+/// Relative devices don't report where the pointer is, so the tracker starts from the top left
+/// corner (the caller must move the pointer there) and follows accelerated motion, which is what
+/// the compositor moves the pointer by. Absolute devices reset the position.
+pub struct PointerTracker {
+    screen: ScreenSize,
+    x: f64,
+    y: f64,
+}
+
+impl PointerTracker {
+    pub fn new(screen: ScreenSize) -> PointerTracker {
+        PointerTracker {
+            screen: screen,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    /// Convert a libinput event like RecordedEvent::from_event, except that pointer motion is
+    /// recorded as the absolute position it moves the pointer to.
+    pub fn convert(&mut self, event: &Event) -> Option<RecordedEvent> {
+        let width = self.screen.width as f64;
+        let height = self.screen.height as f64;
+
+        let time_usec = match event {
+            &Pointer(Motion(ref motion_event)) => {
+                self.x = (self.x + motion_event.dx()).max(0.0).min(width - 1.0);
+                self.y = (self.y + motion_event.dy()).max(0.0).min(height - 1.0);
+                motion_event.time_usec()
+            },
+            &Pointer(MotionAbsolute(ref motion_event)) => {
+                self.x = motion_event.absolute_x_transformed(self.screen.width);
+                self.y = motion_event.absolute_y_transformed(self.screen.height);
+                motion_event.time_usec()
+            },
+            e => return RecordedEvent::from_event(e),
+        };
+
+        Some(RecordedEvent::Absolute {
+            time_usec: time_usec,
+            x: self.x / width,
+            y: self.y / height,
+        })
+    }
+}
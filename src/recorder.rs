@@ -0,0 +1,275 @@
+//! Capturing libinput events into a recording.
+use device::{self, DeviceFilter};
+use hotkey::{Hotkey, Modifier};
+use input::event::{Event, EventTrait};
+use pointer::{PointerTracker, ScreenSize};
+use recording::RecordedEvent;
+use std::mem;
+use std::time::Duration;
+use timer;
+use uinput::Key;
+
+/// Collects events between a start and a stop.
+pub struct Recorder {
+    events: Vec<RecordedEvent>,
+    recording: bool,
+    start_time: Duration,
+    tracker: Option<PointerTracker>,
+    devices: Vec<DeviceFilter>,
+}
+
+impl Recorder {
+    /// Create a recorder capturing events from the devices matching any of the filters, or from
+    /// every device if there are none.
+    pub fn new(devices: Vec<DeviceFilter>) -> Recorder {
+        Recorder {
+            events: Vec::new(),
+            recording: false,
+            start_time: Duration::new(0, 0),
+            tracker: None,
+            devices: devices,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Time the current recording started, on the monotonic clock.
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// Start a new recording, discarding any events of an unfinished one. The recording starts with
+    /// a start marker, so the time until the first input is kept.
+    /// If a screen size is given, pointer motion is recorded as absolute positions on that screen,
+    /// starting from the top left corner.
+    pub fn start(&mut self, screen: Option<ScreenSize>) {
+        self.events.clear();
+        self.start_time = timer::monotonic_now();
+        self.events.push(RecordedEvent::Start { time_usec: timer::as_usec(self.start_time) });
+        self.tracker = screen.map(PointerTracker::new);
+        self.recording = true;
+        println!("Started recording!");
+    }
+
+    /// Stop recording and return the recorded events, ending with a stop marker. Key and button
+    /// events without their other half are left out, see trim_unmatched, as are the modifiers of
+    /// the hotkey that stopped the recording, if any, see trim_hotkey_modifiers.
+    pub fn stop(&mut self, hotkey: Option<&Hotkey>) -> Vec<RecordedEvent> {
+        self.events.push(RecordedEvent::Stop { time_usec: timer::as_usec(timer::monotonic_now()) });
+        self.recording = false;
+        println!("Stopped recording!");
+
+        let mut events = mem::replace(&mut self.events, Vec::new());
+        trim_unmatched(&mut events);
+        if let Some(hotkey) = hotkey {
+            trim_hotkey_modifiers(&mut events, hotkey);
+        }
+        events
+    }
+
+    /// Add an event to the recording. Does nothing if not recording or if the event comes from a
+    /// device that isn't recorded.
+    pub fn capture(&mut self, event: &Event) {
+        if !self.recording || !device::any_matches(&self.devices, &event.device()) {
+            return;
+        }
+
+        let recorded = match self.tracker {
+            Some(ref mut tracker) => tracker.convert(event),
+            None => RecordedEvent::from_event(event),
+        };
+        self.events.extend(recorded);
+    }
+}
+
+/// Returns the code of a key or button event and whether it was pressed.
+fn key_state(event: &RecordedEvent) -> Option<(u16, bool)> {
+    match *event {
+        RecordedEvent::Key { code, pressed, .. } | RecordedEvent::Button { code, pressed, .. } => Some((code, pressed)),
+        _ => None,
+    }
+}
+
+/// Remove key and button events whose other half isn't recorded: releases of keys held when the
+/// recording started, like the modifiers of the record key, and presses of keys still held when it
+/// stopped, like the modifiers of the hotkey that stopped it. Replays then start and end with
+/// nothing held.
+fn trim_unmatched(events: &mut Vec<RecordedEvent>) {
+    let mut keep = vec![true; events.len()];
+
+    let mut held = Vec::new();
+    for (i, e) in events.iter().enumerate() {
+        match key_state(e) {
+            Some((code, true)) => if !held.contains(&code) { held.push(code) },
+            Some((code, false)) if held.contains(&code) => held.retain(|&c| c != code),
+            Some((_, false)) => keep[i] = false,
+            None => {},
+        }
+    }
+
+    // The same from the end, with the roles of presses and releases swapped.
+    let mut released = Vec::new();
+    for (i, e) in events.iter().enumerate().rev() {
+        if !keep[i] {
+            continue;
+        }
+        match key_state(e) {
+            Some((code, false)) => if !released.contains(&code) { released.push(code) },
+            Some((code, true)) if released.contains(&code) => released.retain(|&c| c != code),
+            Some((_, true)) => keep[i] = false,
+            None => {},
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    events.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Returns whether the event is a key event for one of the modifiers of the hotkey.
+fn is_hotkey_modifier(event: &RecordedEvent, hotkey: &Hotkey) -> bool {
+    match *event {
+        RecordedEvent::Key { code, .. } => Key::from_code(code).ok()
+            .and_then(Modifier::from_key)
+            .map_or(false, |modifier| hotkey.modifiers().contains(&modifier)),
+        _ => false,
+    }
+}
+
+/// Remove the modifiers of the hotkey that stopped the recording that were pressed after the last
+/// other input, along with their releases. trim_unmatched only catches them when they're still held
+/// as the recording stops, not when they're let go before the hotkey's key, like Alt in
+/// Alt↓ R↓ Alt↑ R↑ for Alt+R, which would otherwise end every replay with an Alt tap.
+fn trim_hotkey_modifiers(events: &mut Vec<RecordedEvent>, hotkey: &Hotkey) {
+    let end = events.iter()
+        .rposition(|e| !e.is_marker() && !is_hotkey_modifier(e, hotkey))
+        .map_or(0, |i| i + 1);
+
+    // Only modifier key events and markers are left after the last other input.
+    let pressed: Vec<u16> = events[end..].iter()
+        .filter_map(|e| match key_state(e) {
+            Some((code, true)) => Some(code),
+            _ => None,
+        })
+        .collect();
+
+    let mut i = 0;
+    events.retain(|e| {
+        let keep = i < end || !key_state(e).map_or(false, |(code, _)| pressed.contains(&code));
+        i += 1;
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use recording::tests::{button_recording, button_sequences, button_transitions, BTN_RIGHT};
+    use uinput::BTN_LEFT;
+
+    fn key(time_usec: u64, key: Key, pressed: bool) -> RecordedEvent {
+        let code: u8 = key.into();
+        RecordedEvent::Key { time_usec: time_usec, code: code as u16, pressed: pressed }
+    }
+
+    #[test]
+    fn keeps_held_buttons() {
+        for sequence in button_sequences() {
+            let mut events = button_recording(&sequence);
+            trim_unmatched(&mut events);
+
+            assert_eq!(events, button_recording(&sequence));
+            assert_eq!(button_transitions(&events), sequence);
+        }
+    }
+
+    #[test]
+    fn trims_the_unmatched_half_of_a_repeated_press() {
+        // Left was held when recording started, pressed again after its release while right is
+        // held, and still held when recording stopped.
+        let mut events = button_recording(&[
+            (BTN_RIGHT, true), (BTN_LEFT, false), (BTN_LEFT, true), (BTN_RIGHT, false),
+        ]);
+        trim_unmatched(&mut events);
+
+        assert_eq!(button_transitions(&events), vec![(BTN_RIGHT, true), (BTN_RIGHT, false)]);
+    }
+
+    #[test]
+    fn trims_each_button_separately() {
+        // Right was held when recording started and left is still held when it stopped.
+        let mut events = vec![
+            RecordedEvent::Button { time_usec: 100, code: BTN_LEFT, pressed: true },
+            RecordedEvent::Button { time_usec: 200, code: BTN_RIGHT, pressed: false },
+            RecordedEvent::Button { time_usec: 300, code: BTN_RIGHT, pressed: true },
+            RecordedEvent::Button { time_usec: 400, code: BTN_LEFT, pressed: false },
+            RecordedEvent::Button { time_usec: 500, code: BTN_LEFT, pressed: true },
+        ];
+        trim_unmatched(&mut events);
+
+        assert_eq!(button_transitions(&events), vec![(BTN_LEFT, true), (BTN_LEFT, false)]);
+    }
+
+    #[test]
+    fn trims_modifiers_released_before_the_stop_key() {
+        // Alt+R stopped the recording, with Alt let go before R: Alt↓ R↓ Alt↑ R↑.
+        let hotkey = "Alt+R".parse().unwrap();
+        let mut events = vec![
+            RecordedEvent::Start { time_usec: 0 },
+            key(100, Key::X, true),
+            key(200, Key::X, false),
+            key(300, Key::LeftAlt, true),
+            key(500, Key::LeftAlt, false),
+            RecordedEvent::Stop { time_usec: 700 },
+        ];
+        trim_hotkey_modifiers(&mut events, &hotkey);
+
+        assert_eq!(events, vec![
+            RecordedEvent::Start { time_usec: 0 },
+            key(100, Key::X, true),
+            key(200, Key::X, false),
+            RecordedEvent::Stop { time_usec: 700 },
+        ]);
+    }
+
+    #[test]
+    fn keeps_modifiers_pressed_before_other_input() {
+        // Alt was held for Alt+X before Alt+R stopped the recording: Alt↓ X↓ X↑ R↓ Alt↑ R↑.
+        let hotkey = "Alt+R".parse().unwrap();
+        let mut events = vec![
+            RecordedEvent::Start { time_usec: 0 },
+            key(100, Key::LeftAlt, true),
+            key(200, Key::X, true),
+            key(300, Key::X, false),
+            key(500, Key::LeftAlt, false),
+            RecordedEvent::Stop { time_usec: 700 },
+        ];
+        let expected = events.clone();
+        trim_hotkey_modifiers(&mut events, &hotkey);
+
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn keeps_modifiers_of_other_hotkeys() {
+        // Shift isn't part of Alt+R, so a Shift tap at the end stays.
+        let hotkey = "Alt+R".parse().unwrap();
+        let mut events = vec![
+            RecordedEvent::Start { time_usec: 0 },
+            key(100, Key::LeftShift, true),
+            key(200, Key::LeftShift, false),
+            key(300, Key::LeftAlt, true),
+            key(500, Key::LeftAlt, false),
+            RecordedEvent::Stop { time_usec: 700 },
+        ];
+        trim_hotkey_modifiers(&mut events, &hotkey);
+
+        assert_eq!(events, vec![
+            RecordedEvent::Start { time_usec: 0 },
+            key(100, Key::LeftShift, true),
+            key(200, Key::LeftShift, false),
+            RecordedEvent::Stop { time_usec: 700 },
+        ]);
+    }
+}
@@ -0,0 +1,356 @@
+//! Recorded input events and their on-disk representation.
+//!
+//! A recording file is plain text. The first line is a header naming the format and its version:
+//!
+//! ```text
+//! swan-ag-recording 1
+//! ```
+//!
+//! Every following line holds one event: its libinput timestamp in microseconds, the event kind
+//! and the kind specific fields, separated by whitespace.
+//!
+//! ```text
+//! <time_usec> key <code> <pressed>
+//! <time_usec> button <code> <pressed>
+//! <time_usec> motion <dx> <dy>
+//! <time_usec> scroll <vertical> <horizontal>
+//! <time_usec> absolute <x> <y>
+//! <time_usec> start
+//! <time_usec> stop
+//! ```
+//!
+//! Key and button codes are evdev codes (see linux/include/uapi/linux/input-event-codes.h) and
+//! `pressed` is `1` or `0`. Motion and scroll values are in libinput units, with scrolling down
+//! and right being positive and a wheel click scrolling 15. Absolute positions are fractions of the
+//! device's range, from 0.0 to 1.0. `start` and `stop` mark when recording started and stopped,
+//! on the same clock as the events. Blank lines and lines starting with `#` are ignored.
+use input::Event::{Keyboard, Pointer};
+use input::event::Event;
+use input::event::KeyboardEvent::Key;
+use input::event::PointerEvent::{Axis, Button, Motion, MotionAbsolute};
+use input::event::keyboard::{KeyboardEventTrait, KeyState};
+use input::event::pointer::{self, ButtonState, PointerEventTrait};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Name written on the first line of every recording file.
+const FORMAT_NAME: &'static str = "swan-ag-recording";
+/// Version of the recording format written by this build.
+pub const FORMAT_VERSION: u32 = 1;
+/// Scroll distance of a wheel click. This is libinput's default click angle.
+pub const WHEEL_CLICK: f64 = 15.0;
+
+/// An input event owned by swan-ag, independent of the libinput context it was captured from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordedEvent {
+    Key { time_usec: u64, code: u16, pressed: bool },
+    Button { time_usec: u64, code: u16, pressed: bool },
+    Motion { time_usec: u64, dx: f64, dy: f64 },
+    Scroll { time_usec: u64, vertical: f64, horizontal: f64 },
+    Absolute { time_usec: u64, x: f64, y: f64 },
+    /// Recording started. Marks the idle time before the first input.
+    Start { time_usec: u64 },
+    /// Recording stopped. Marks the idle time after the last input.
+    Stop { time_usec: u64 },
+}
+
+impl RecordedEvent {
+    /// Convert a libinput event, returning None for events that are not recorded.
+    pub fn from_event(event: &Event) -> Option<RecordedEvent> {
+        match event {
+            &Keyboard(Key(ref key_event)) => {
+                Some(RecordedEvent::Key {
+                    time_usec: key_event.time_usec(),
+                    code: key_event.key() as u16,
+                    pressed: key_event.key_state() == KeyState::Pressed,
+                })
+            },
+            &Pointer(Motion(ref motion_event)) => {
+                Some(RecordedEvent::Motion {
+                    time_usec: motion_event.time_usec(),
+                    dx: motion_event.dx_unaccelerated(),
+                    dy: motion_event.dy_unaccelerated(),
+                })
+            },
+            &Pointer(MotionAbsolute(ref motion_event)) => {
+                // Transforming against a size of 1 gives the position as a fraction of the range.
+                Some(RecordedEvent::Absolute {
+                    time_usec: motion_event.time_usec(),
+                    x: motion_event.absolute_x_transformed(1),
+                    y: motion_event.absolute_y_transformed(1),
+                })
+            },
+            &Pointer(Button(ref button_event)) => {
+                // The seat button count includes this button held on other devices of the seat,
+                // so only the button state says whether this event pressed or released it.
+                Some(RecordedEvent::Button {
+                    time_usec: button_event.time_usec(),
+                    code: button_event.button() as u16,
+                    pressed: button_event.button_state() == ButtonState::Pressed,
+                })
+            },
+            &Pointer(Axis(ref axis_event)) => {
+                // Wheels are recorded in clicks so mice with other click angles replay the same.
+                let value = |axis| if !axis_event.has_axis(axis) {
+                    0.0
+                } else if let Some(clicks) = axis_event.axis_value_discrete(axis) {
+                    clicks * WHEEL_CLICK
+                } else {
+                    axis_event.axis_value(axis)
+                };
+
+                Some(RecordedEvent::Scroll {
+                    time_usec: axis_event.time_usec(),
+                    vertical: value(pointer::Axis::Vertical),
+                    horizontal: value(pointer::Axis::Horizontal),
+                })
+            },
+            _ => None,
+        }
+    }
+
+    /// Time of the event in microseconds.
+    pub fn time_usec(&self) -> u64 {
+        match *self {
+            RecordedEvent::Key { time_usec, .. } |
+            RecordedEvent::Button { time_usec, .. } |
+            RecordedEvent::Motion { time_usec, .. } |
+            RecordedEvent::Scroll { time_usec, .. } |
+            RecordedEvent::Absolute { time_usec, .. } |
+            RecordedEvent::Start { time_usec } |
+            RecordedEvent::Stop { time_usec } => time_usec,
+        }
+    }
+
+    /// Returns whether this marks the start or stop of the recording rather than being input.
+    pub fn is_marker(&self) -> bool {
+        match *self {
+            RecordedEvent::Start { .. } | RecordedEvent::Stop { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Write events to the given file, replacing it if it exists.
+pub fn save<P: AsRef<Path>>(path: P, events: &[RecordedEvent]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "{} {}", FORMAT_NAME, FORMAT_VERSION)?;
+    for e in events {
+        match *e {
+            RecordedEvent::Key { time_usec, code, pressed } => {
+                writeln!(writer, "{} key {} {}", time_usec, code, pressed as u8)?
+            },
+            RecordedEvent::Button { time_usec, code, pressed } => {
+                writeln!(writer, "{} button {} {}", time_usec, code, pressed as u8)?
+            },
+            RecordedEvent::Motion { time_usec, dx, dy } => {
+                writeln!(writer, "{} motion {} {}", time_usec, dx, dy)?
+            },
+            RecordedEvent::Scroll { time_usec, vertical, horizontal } => {
+                writeln!(writer, "{} scroll {} {}", time_usec, vertical, horizontal)?
+            },
+            RecordedEvent::Absolute { time_usec, x, y } => {
+                writeln!(writer, "{} absolute {} {}", time_usec, x, y)?
+            },
+            RecordedEvent::Start { time_usec } => writeln!(writer, "{} start", time_usec)?,
+            RecordedEvent::Stop { time_usec } => writeln!(writer, "{} stop", time_usec)?,
+        }
+    }
+
+    writer.flush()
+}
+
+/// Read events from the given file.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+
+    let header = match lines.next() {
+        Some(line) => line?,
+        None => return Err(invalid_data("empty recording file")),
+    };
+    let version = parse_header(&header)?;
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(&format!("unsupported recording version {}", version)));
+    }
+
+    let mut events = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_line(line) {
+            Some(event) => events.push(event),
+            // Line numbers are 1-based and the header was line 1.
+            None => return Err(invalid_data(&format!("malformed event on line {}", n + 2))),
+        }
+    }
+
+    Ok(events)
+}
+
+/// Returns the format version named by a header line.
+fn parse_header(line: &str) -> io::Result<u32> {
+    let mut fields = line.split_whitespace();
+
+    if fields.next() != Some(FORMAT_NAME) {
+        return Err(invalid_data("not a swan-ag recording"));
+    }
+
+    match fields.next().and_then(|v| v.parse().ok()) {
+        Some(version) => Ok(version),
+        None => Err(invalid_data("missing recording version")),
+    }
+}
+
+/// Parse an event line.
+fn parse_line(line: &str) -> Option<RecordedEvent> {
+    let mut fields = line.split_whitespace();
+
+    let time_usec = fields.next()?.parse().ok()?;
+    let kind = fields.next()?;
+    match kind {
+        "start" | "stop" if fields.next().is_some() => return None,
+        "start" => return Some(RecordedEvent::Start { time_usec: time_usec }),
+        "stop" => return Some(RecordedEvent::Stop { time_usec: time_usec }),
+        _ => {},
+    }
+    let a = fields.next()?;
+    let b = fields.next()?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let event = match kind {
+        "key" => RecordedEvent::Key {
+            time_usec: time_usec,
+            code: a.parse().ok()?,
+            pressed: parse_pressed(b)?,
+        },
+        "button" => RecordedEvent::Button {
+            time_usec: time_usec,
+            code: a.parse().ok()?,
+            pressed: parse_pressed(b)?,
+        },
+        "motion" => RecordedEvent::Motion {
+            time_usec: time_usec,
+            dx: a.parse().ok()?,
+            dy: b.parse().ok()?,
+        },
+        "scroll" => RecordedEvent::Scroll {
+            time_usec: time_usec,
+            vertical: a.parse().ok()?,
+            horizontal: b.parse().ok()?,
+        },
+        "absolute" => RecordedEvent::Absolute {
+            time_usec: time_usec,
+            x: a.parse().ok()?,
+            y: b.parse().ok()?,
+        },
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+fn parse_pressed(field: &str) -> Option<bool> {
+    match field {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use uinput::BTN_LEFT;
+
+    pub const BTN_RIGHT: u16 = BTN_LEFT + 1;
+    pub const BTN_MIDDLE: u16 = BTN_LEFT + 2;
+    pub const BTN_SIDE: u16 = BTN_LEFT + 3;
+    pub const BTN_EXTRA: u16 = BTN_LEFT + 4;
+
+    /// Button presses and releases where several buttons are held at once, as (code, pressed).
+    pub fn button_sequences() -> Vec<Vec<(u16, bool)>> {
+        vec![
+            // Released in the order they were pressed.
+            vec![(BTN_LEFT, true), (BTN_RIGHT, true), (BTN_LEFT, false), (BTN_RIGHT, false)],
+            // Released in reverse order.
+            vec![(BTN_LEFT, true), (BTN_RIGHT, true), (BTN_RIGHT, false), (BTN_LEFT, false)],
+            // Three held at once.
+            vec![(BTN_MIDDLE, true), (BTN_SIDE, true), (BTN_EXTRA, true),
+                 (BTN_SIDE, false), (BTN_MIDDLE, false), (BTN_EXTRA, false)],
+            // Left pressed again while right is still held.
+            vec![(BTN_LEFT, true), (BTN_RIGHT, true), (BTN_LEFT, false),
+                 (BTN_LEFT, true), (BTN_RIGHT, false), (BTN_LEFT, false)],
+        ]
+    }
+
+    /// Returns a recording of the given button presses and releases, 100us apart.
+    pub fn button_recording(transitions: &[(u16, bool)]) -> Vec<RecordedEvent> {
+        let mut events = vec![RecordedEvent::Start { time_usec: 1000 }];
+        for (i, &(code, pressed)) in transitions.iter().enumerate() {
+            events.push(RecordedEvent::Button { time_usec: 1100 + 100 * i as u64, code: code, pressed: pressed });
+        }
+        events.push(RecordedEvent::Stop { time_usec: 1100 + 100 * transitions.len() as u64 });
+        events
+    }
+
+    /// Returns the code and state of every button event, in order.
+    pub fn button_transitions(events: &[RecordedEvent]) -> Vec<(u16, bool)> {
+        events.iter().filter_map(|e| match *e {
+            RecordedEvent::Button { code, pressed, .. } => Some((code, pressed)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn held_buttons_survive_save_and_load() {
+        for (i, sequence) in button_sequences().iter().enumerate() {
+            let path = env::temp_dir().join(format!("swan-ag-test-{}-{}.swan", process::id(), i));
+            let events = button_recording(sequence);
+
+            save(&path, &events).unwrap();
+            let loaded = load(&path);
+            fs::remove_file(&path).ok();
+
+            let loaded = loaded.unwrap();
+            assert_eq!(loaded, events);
+            assert_eq!(&button_transitions(&loaded), sequence);
+        }
+    }
+
+    #[test]
+    fn saves_button_lines() {
+        let path = env::temp_dir().join(format!("swan-ag-test-{}-lines.swan", process::id()));
+        save(&path, &button_recording(&button_sequences()[1])).unwrap();
+        let mut text = String::new();
+        File::open(&path).and_then(|mut file| io::Read::read_to_string(&mut file, &mut text)).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(text, "swan-ag-recording 1\n1000 start\n1100 button 272 1\n1200 button 273 1\n\
+                          1300 button 273 0\n1400 button 272 0\n1500 stop\n");
+    }
+
+    #[test]
+    fn parses_button_lines() {
+        assert_eq!(parse_line("1200 button 273 1"),
+                   Some(RecordedEvent::Button { time_usec: 1200, code: BTN_RIGHT, pressed: true }));
+        assert_eq!(parse_line("1300 button 272 0"),
+                   Some(RecordedEvent::Button { time_usec: 1300, code: BTN_LEFT, pressed: false }));
+        assert_eq!(parse_line("1300 button 272 2"), None);
+    }
+}
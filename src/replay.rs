@@ -0,0 +1,395 @@
+//! Replaying recorded events through uinput.
+use abort;
+use cli::Options;
+use cue::{self, Cue};
+use recording::{self, RecordedEvent};
+use std::cell::Cell;
+use std::str::FromStr;
+use std::time::Duration;
+use timer;
+use uinput::{self, EventType, UInput};
+
+/// Idle time before the first or after the last input of a replay, written as a number of seconds
+/// or as "original" for the recorded idle time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IdleTime {
+    /// The time between the start or stop of the recording and the input, at the replay speed.
+    /// Recordings without start and stop markers have no idle time.
+    Original,
+    Fixed(Duration),
+}
+
+impl IdleTime {
+    /// Returns the idle time of a replay, given the recorded idle time if known.
+    fn replayed(&self, recorded_usec: Option<u64>, speed: f64) -> Duration {
+        match *self {
+            IdleTime::Original => scale(recorded_usec.unwrap_or(0), speed),
+            IdleTime::Fixed(duration) => duration,
+        }
+    }
+}
+
+impl FromStr for IdleTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IdleTime, String> {
+        if s == "original" {
+            return Ok(IdleTime::Original);
+        }
+
+        match s.parse::<f64>() {
+            Ok(secs) if timer::is_valid_secs(secs) => {
+                Ok(IdleTime::Fixed(timer::from_secs(secs)))
+            },
+            _ => Err(format!("Expected a number of seconds or original, got {}", s)),
+        }
+    }
+}
+
+/// Splits scrolling into the high resolution and wheel click values uinput expects, carrying the
+/// remainders over to the next scroll.
+#[derive(Default)]
+struct WheelAccumulator {
+    hi_res_err: f64, // Fraction of a high resolution unit not yet sent
+    clicks_err: i32, // High resolution units not yet sent as a click
+}
+
+impl WheelAccumulator {
+    /// Returns the high resolution value and number of clicks to send for a scroll distance given
+    /// in 120ths of a click.
+    fn add(&mut self, hi_res: f64) -> (i32, i32) {
+        self.hi_res_err += hi_res;
+        let hi_res = self.hi_res_err.trunc();
+        self.hi_res_err -= hi_res;
+
+        self.clicks_err += hi_res as i32;
+        let clicks = self.clicks_err / 120;
+        self.clicks_err -= clicks * 120;
+
+        (hi_res as i32, clicks)
+    }
+}
+
+/// Turns recorded events into the evdev events that replay them, carrying the fractions of motion
+/// and scrolling that can't be sent yet over to later events.
+struct Translator<'a> {
+    options: &'a Options,
+    positioned: bool, // Whether an absolute position was sent yet
+    pointer_err: (f64, f64), // Total accumulated positional error
+    vertical_wheel: WheelAccumulator,
+    horizontal_wheel: WheelAccumulator,
+}
+
+impl<'a> Translator<'a> {
+    fn new(options: &'a Options) -> Translator<'a> {
+        Translator {
+            options: options,
+            positioned: false,
+            pointer_err: (0.0, 0.0),
+            vertical_wheel: WheelAccumulator::default(),
+            horizontal_wheel: WheelAccumulator::default(),
+        }
+    }
+
+    /// Returns the evdev events that replay a recorded event, without the EV_SYN that ends them.
+    fn translate(&mut self, event: &RecordedEvent) -> Vec<(EventType, u16, i32)> {
+        let mut out = Vec::new();
+
+        match *event {
+            RecordedEvent::Key { code, pressed, .. } => {
+                match uinput::Key::from_code(code) {
+                    Ok(_) => out.push((EventType::EV_KEY, code, pressed as i32)),
+                    Err(e) => println!("Skipping key event: {}", e),
+                }
+            },
+            RecordedEvent::Motion { dx: x, dy: y, .. } => {
+                // This assumes that the units from libinput are the same as that of the uinput
+                // device. This is WRONG and doesn't work for some devices. (i.e. my touchpad)
+                // Using accelerated data makes touchpads work slightly better but makes worse
+                // mouse control.
+                out.push((EventType::EV_REL, uinput::REL_X, x as i32));
+                out.push((EventType::EV_REL, uinput::REL_Y, y as i32));
+
+                // Though unaccelerated data is typically integers.
+                self.pointer_err.0 += x.fract();
+                self.pointer_err.1 += y.fract();
+
+                if self.pointer_err.0.abs() > 1.0 {
+                    out.push((EventType::EV_REL, uinput::REL_X, self.pointer_err.0 as i32)); // Sends 1 or -1
+                    self.pointer_err.0 -= self.pointer_err.0.trunc(); // Subtracts 1 or -1.
+                }
+                if self.pointer_err.1.abs() > 1.0 {
+                    out.push((EventType::EV_REL, uinput::REL_Y, self.pointer_err.1 as i32)); // Sends 1 or -1
+                    self.pointer_err.1 -= self.pointer_err.1.trunc(); // Subtracts 1 or -1.
+                }
+            },
+            RecordedEvent::Button { code, pressed, .. } => {
+                if code < uinput::BTN_LEFT || code > uinput::BTN_TASK {
+                    println!("Unimplemented button event!");
+                } else {
+                    out.push((EventType::EV_KEY, code, pressed as i32));
+                }
+            },
+            RecordedEvent::Scroll { vertical, horizontal, .. } => {
+                // libinput scrolls down for positive values, the evdev wheel scrolls up.
+                let (hi_res, clicks) = self.vertical_wheel.add(-vertical * 120.0 / recording::WHEEL_CLICK);
+                if hi_res != 0 {
+                    out.push((EventType::EV_REL, uinput::REL_WHEEL_HI_RES, hi_res));
+                }
+                if clicks != 0 {
+                    out.push((EventType::EV_REL, uinput::REL_WHEEL, clicks));
+                }
+
+                let (hi_res, clicks) = self.horizontal_wheel.add(horizontal * 120.0 / recording::WHEEL_CLICK);
+                if hi_res != 0 {
+                    out.push((EventType::EV_REL, uinput::REL_HWHEEL_HI_RES, hi_res));
+                }
+                if clicks != 0 {
+                    out.push((EventType::EV_REL, uinput::REL_HWHEEL, clicks));
+                }
+            },
+            RecordedEvent::Absolute { x, y, .. } => {
+                match self.options.screen {
+                    Some(screen) if self.options.absolute => {
+                        // A position of 1.0 is the far edge, one past the last pixel.
+                        let x = ((x * screen.width as f64) as i32).max(0).min(screen.width as i32 - 1);
+                        let y = ((y * screen.height as f64) as i32).max(0).min(screen.height as i32 - 1);
+
+                        // The kernel drops values equal to the axis's current one, which the
+                        // device may still have from an earlier replay while the pointer was moved
+                        // since. Moving next to the position first makes sure it's sent.
+                        if !self.positioned {
+                            let beside = |v: i32| if v > 0 { v - 1 } else { v + 1 };
+                            out.push((EventType::EV_ABS, uinput::ABS_X, beside(x)));
+                            out.push((EventType::EV_ABS, uinput::ABS_Y, beside(y)));
+                            out.push((EventType::EV_SYN, 0, 0));
+                            self.positioned = true;
+                        }
+                        out.push((EventType::EV_ABS, uinput::ABS_X, x));
+                        out.push((EventType::EV_ABS, uinput::ABS_Y, y));
+                    },
+                    _ => println!("Absolute events can only be replayed with --absolute!"),
+                }
+            },
+            RecordedEvent::Start { .. } | RecordedEvent::Stop { .. } => {},
+        }
+
+        out
+    }
+}
+
+/// How late events were sent compared to when they were scheduled.
+#[derive(Default)]
+struct Jitter {
+    events: u32,
+    total: Duration,
+    max: Duration,
+}
+
+impl Jitter {
+    fn add(&mut self, late: Duration) {
+        self.events += 1;
+        self.total += late;
+        self.max = self.max.max(late);
+    }
+
+    fn report(&self) {
+        if self.events == 0 {
+            return;
+        }
+
+        let mean = self.total / self.events;
+        println!("Replayed {} events, late by {}us on average and {}us at most.",
+                 self.events, timer::as_usec(mean), timer::as_usec(self.max));
+    }
+}
+
+/// Replay events in the event store.
+/// Modifies the pointer position.
+///
+/// The first input is sent after the --lead-in idle time. Every later input is sent when the time
+/// since the first input, divided by the speed, has passed since then. Deadlines are absolute, so
+/// time spent sending events doesn't delay the rest. The replay returns after the --tail idle time
+/// following the last input.
+///
+/// The replay stops as soon as an abort is requested. Keys and buttons held when it stops, also
+/// because the recording ended with them held, are released. `poll` is called while waiting for
+/// the next event, at least every few milliseconds.
+pub fn replay_events<F>(options: &Options, events: &[RecordedEvent], uinput: &mut UInput, mut poll: F)
+    -> uinput::Result<()>
+    where F: FnMut(&UInput)
+{
+    println!("Replay!");
+    let start = timer::monotonic_now();
+
+    let mut inputs = events.iter().filter(|e| !e.is_marker()).map(|e| e.time_usec());
+    let first_input_time = inputs.next().unwrap_or(0);
+    let last_input_time = inputs.last().unwrap_or(first_input_time);
+    let start_marker = events.iter().filter_map(|e| match *e {
+        RecordedEvent::Start { time_usec } => Some(time_usec),
+        _ => None,
+    }).next();
+    let stop_marker = events.iter().filter_map(|e| match *e {
+        RecordedEvent::Stop { time_usec } => Some(time_usec),
+        _ => None,
+    }).last();
+
+    let lead_in = options.lead_in.replayed(start_marker.map(|t| first_input_time.saturating_sub(t)), options.speed);
+    let tail = options.tail.replayed(stop_marker.map(|t| t.saturating_sub(last_input_time)), options.speed);
+    let first_deadline = start + lead_in;
+
+    let mut jitter = Jitter::default();
+    let mut translator = Translator::new(options);
+
+    for e in events {
+        if e.is_marker() {
+            continue;
+        }
+
+        // Events recorded out of order are sent right away, keeping their order.
+        let deadline = first_deadline + scale(e.time_usec().saturating_sub(first_input_time), options.speed);
+        if !abort::sleep_until_polling(deadline, || poll(uinput)) {
+            println!("Replay aborted!");
+            return uinput.release_all();
+        }
+        if options.speed != 0.0 {
+            jitter.add(timer::monotonic_now().checked_sub(deadline).unwrap_or(Duration::new(0, 0)));
+        }
+
+        for (kind, code, value) in translator.translate(e) {
+            uinput.emit(kind, code, value)?;
+        }
+        // For some events like motion, this is not necessary.
+        uinput.sync()?;
+    }
+    jitter.report();
+    uinput.release_all()?;
+
+    let end = first_deadline + scale(last_input_time.saturating_sub(first_input_time), options.speed) + tail;
+    abort::sleep_until_polling(end, || poll(uinput));
+
+    Ok(())
+}
+
+/// Replay events --repeat times, or until aborted with --loop, waiting --repeat-delay seconds
+/// between replays. `input` is called while the replays wait, to process the input that arrived
+/// meanwhile, and returns whether the abort key was pressed, which stops repeating after the
+/// current replay. The panic key stops the replays right away. The replay start and stop cues are
+/// given once around all the replays. Recordings without input aren't replayed at all.
+pub fn replay_repeated<F>(options: &Options, events: &[RecordedEvent], uinput: &mut UInput, input: F)
+    -> uinput::Result<()>
+    where F: FnMut(&UInput) -> bool
+{
+    // Replays without input return at once, so --loop would spin.
+    if events.iter().all(|e| e.is_marker()) {
+        println!("The recording is empty, there's nothing to replay.");
+        return Ok(());
+    }
+
+    cue::notify(options, Cue::ReplayStart);
+    let result = replay_until_done(options, events, uinput, input);
+    cue::notify(options, Cue::ReplayStop);
+    result
+}
+
+fn replay_until_done<F>(options: &Options, events: &[RecordedEvent], uinput: &mut UInput, mut input: F)
+    -> uinput::Result<()>
+    where F: FnMut(&UInput) -> bool
+{
+    let abort_key = Cell::new(false);
+    let mut poll = |uinput: &UInput| if input(uinput) {
+        abort_key.set(true);
+    };
+    let delay = timer::from_secs(options.repeat_delay);
+    let mut replays = 0;
+    abort::reset();
+
+    loop {
+        if let Err(e) = replay_events(options, events, uinput, &mut poll) {
+            // Writing failed, but releasing may still work.
+            uinput.release_all().ok();
+            return Err(e);
+        }
+        replays += 1;
+        if abort::requested() || !options.loop_replay && replays >= options.repeat {
+            return Ok(());
+        }
+
+        if abort_key.get() || !abort::sleep_until_polling(timer::monotonic_now() + delay, || poll(uinput)) ||
+           abort_key.get() {
+            println!("Aborted after {} replays.", replays);
+            return Ok(());
+        }
+    }
+}
+
+/// Returns how long recorded time takes to replay at the given speed. Nothing takes time at speed 0.
+fn scale(usec: u64, speed: f64) -> Duration {
+    if speed == 0.0 {
+        return Duration::new(0, 0);
+    }
+
+    timer::from_usec((usec as f64 / speed) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pointer::ScreenSize;
+    use recording::tests::{button_recording, button_sequences, BTN_RIGHT};
+    use uinput::BTN_LEFT;
+
+    /// Returns the evdev events replaying the given events sends.
+    fn translate_all(options: &Options, events: &[RecordedEvent]) -> Vec<(EventType, u16, i32)> {
+        let mut translator = Translator::new(options);
+        events.iter().flat_map(|e| translator.translate(e)).collect()
+    }
+
+    #[test]
+    fn replays_held_buttons() {
+        let options = Options::default();
+        for sequence in button_sequences() {
+            let expected: Vec<_> = sequence.iter()
+                .map(|&(code, pressed)| (EventType::EV_KEY, code, pressed as i32))
+                .collect();
+            assert_eq!(translate_all(&options, &button_recording(&sequence)), expected);
+        }
+    }
+
+    #[test]
+    fn replays_a_repeated_press() {
+        let events = button_recording(&[
+            (BTN_LEFT, true), (BTN_RIGHT, true), (BTN_LEFT, false),
+            (BTN_LEFT, true), (BTN_RIGHT, false), (BTN_LEFT, false),
+        ]);
+
+        assert_eq!(translate_all(&Options::default(), &events), vec![
+            (EventType::EV_KEY, BTN_LEFT, 1),
+            (EventType::EV_KEY, BTN_RIGHT, 1),
+            (EventType::EV_KEY, BTN_LEFT, 0),
+            (EventType::EV_KEY, BTN_LEFT, 1),
+            (EventType::EV_KEY, BTN_RIGHT, 0),
+            (EventType::EV_KEY, BTN_LEFT, 0),
+        ]);
+    }
+
+    #[test]
+    fn moves_beside_the_first_position_and_clamps() {
+        let mut options = Options::default();
+        options.absolute = true;
+        options.screen = Some(ScreenSize { width: 1920, height: 1080 });
+        let mut translator = Translator::new(&options);
+
+        assert_eq!(translator.translate(&RecordedEvent::Absolute { time_usec: 0, x: 0.0, y: 1.0 }), vec![
+            (EventType::EV_ABS, uinput::ABS_X, 1),
+            (EventType::EV_ABS, uinput::ABS_Y, 1078),
+            (EventType::EV_SYN, 0, 0),
+            (EventType::EV_ABS, uinput::ABS_X, 0),
+            (EventType::EV_ABS, uinput::ABS_Y, 1079),
+        ]);
+        assert_eq!(translator.translate(&RecordedEvent::Absolute { time_usec: 1, x: 0.5, y: 0.5 }), vec![
+            (EventType::EV_ABS, uinput::ABS_X, 960),
+            (EventType::EV_ABS, uinput::ABS_Y, 540),
+        ]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Timing on the monotonic clock: a timerfd, so timeouts can be waited for with poll alongside the
+//! libinput fd, and sleeping until absolute deadlines.
+use libc;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+
+/// A one-shot timer on the monotonic clock whose fd becomes readable when it expires.
+pub struct Timer {
+    fd: RawFd,
+}
+
+impl Timer {
+    /// Create a disarmed timer.
+    pub fn new() -> io::Result<Timer> {
+        let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Timer { fd: fd })
+    }
+
+    /// Arm the timer to expire when the monotonic clock reaches the given time, replacing any
+    /// earlier expiry, or disarm it. A time that has passed expires right away.
+    pub fn set_deadline(&self, deadline: Option<Duration>) -> io::Result<()> {
+        let value = match deadline {
+            // A zero expiry disarms the timer, so expire as soon as possible instead.
+            Some(deadline) if deadline == Duration::new(0, 0) => libc::timespec { tv_sec: 0, tv_nsec: 1 },
+            Some(deadline) => libc::timespec {
+                tv_sec: deadline.as_secs() as libc::time_t,
+                tv_nsec: deadline.subsec_nanos() as libc::c_long,
+            },
+            None => libc::timespec { tv_sec: 0, tv_nsec: 0 },
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: value,
+        };
+
+        if unsafe { libc::timerfd_settime(self.fd, libc::TFD_TIMER_ABSTIME, &spec, ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Clear an expiry so the fd is no longer readable. Returns whether the timer had expired.
+    pub fn clear(&self) -> bool {
+        let mut expirations = 0u64;
+        let n = unsafe {
+            libc::read(self.fd, &mut expirations as *mut u64 as *mut libc::c_void, 8)
+        };
+
+        n == 8 && expirations > 0
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Longest time in seconds options may be given, so deadlines on the monotonic clock can't overflow.
+const MAX_SECS: f64 = 1e9;
+
+/// Returns whether a number of seconds in floating point can be turned into a duration.
+pub fn is_valid_secs(secs: f64) -> bool {
+    secs.is_finite() && secs >= 0.0 && secs <= MAX_SECS
+}
+
+/// Returns a number of seconds in floating point as a duration.
+pub fn from_secs(secs: f64) -> Duration {
+    if !is_valid_secs(secs) {
+        panic!("Invalid number of seconds: {}", secs);
+    }
+
+    Duration::new(secs as u64, (secs.fract() * 1e9) as u32)
+}
+
+/// Returns a number of microseconds as a duration.
+pub fn from_usec(usec: u64) -> Duration {
+    Duration::new(usec / 1_000_000, (usec % 1_000_000) as u32 * 1000)
+}
+
+/// Returns a duration in whole microseconds.
+pub fn as_usec(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + duration.subsec_nanos() as u64 / 1000
+}
+
+/// Returns the time of the monotonic clock, which libinput timestamps events with.
+pub fn monotonic_now() -> Duration {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now);
+    }
+
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+/// Sleep until the monotonic clock reaches the given time. Returns right away if it already has.
+/// Sleeping until a deadline instead of for a duration keeps errors from adding up over many
+/// sleeps.
+pub fn sleep_until(deadline: Duration) {
+    let deadline = libc::timespec {
+        tv_sec: deadline.as_secs() as libc::time_t,
+        tv_nsec: deadline.subsec_nanos() as libc::c_long,
+    };
+
+    loop {
+        let err = unsafe {
+            libc::clock_nanosleep(libc::CLOCK_MONOTONIC, libc::TIMER_ABSTIME, &deadline, ptr::null_mut())
+        };
+        // Signals interrupt the sleep, in which case it's resumed.
+        if err != libc::EINTR {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_seconds() {
+        assert_eq!(from_secs(2.5), Duration::new(2, 500_000_000));
+        assert_eq!(from_secs(2.0), Duration::new(2, 0));
+        assert_eq!(from_secs(0.0), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn rejects_invalid_seconds() {
+        assert!(is_valid_secs(0.0));
+        assert!(is_valid_secs(2.5));
+        assert!(!is_valid_secs(-1.0));
+        assert!(!is_valid_secs(::std::f64::NAN));
+        assert!(!is_valid_secs(::std::f64::INFINITY));
+        assert!(!is_valid_secs(1e20));
+    }
+
+    #[test]
+    fn converts_microseconds() {
+        assert_eq!(from_usec(2_500_001), Duration::new(2, 500_001_000));
+        assert_eq!(as_usec(Duration::new(2, 500_001_999)), 2_500_001);
+    }
+
+    #[test]
+    fn returns_at_once_from_a_passed_deadline() {
+        let start = monotonic_now();
+        sleep_until(Duration::new(0, 0));
+        sleep_until(start);
+
+        // Generous, so a busy machine doesn't fail the test.
+        assert!(monotonic_now() - start < Duration::from_millis(10));
+    }
+
+    #[test]
+    fn sleeps_until_the_deadline() {
+        let deadline = monotonic_now() + Duration::from_millis(20);
+        sleep_until(deadline);
+
+        assert!(monotonic_now() >= deadline);
+    }
+}
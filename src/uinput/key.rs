@@ -1,3 +1,6 @@
+use std::str::FromStr;
+use super::Error;
+
 /// Keys and buttons
 ///
 /// Most of the keys/buttons are modeled after USB HUT 1.12
@@ -526,9 +529,10 @@ impl From<Key> for u8 {
     }
 }
 
-impl From<u8> for Key {
-    fn from(val: u8) -> Key {
-        match val {
+impl Key {
+    /// Returns the key with the given evdev code.
+    pub fn from_code(code: u16) -> Result<Key, Error> {
+        Ok(match code {
             0 => Key::Reserved,
             1 => Key::Esc,
             2 => Key::One,
@@ -773,7 +777,21 @@ impl From<u8> for Key {
             246 => Key::Wwan,
             247 => Key::RFkill,
             248 => Key::MicMute,
-            _ => panic!("Key from u8 failed."),
-        }
+            _ => return Err(Error::UnknownCode(code)),
+        })
+    }
+}
+
+impl FromStr for Key {
+    type Err = ();
+
+    /// Parse a key from its name, ignoring case. (e.g. "esc", "F2", "LeftCtrl")
+    fn from_str(s: &str) -> Result<Key, ()> {
+        let name = s.to_lowercase();
+
+        (0..249u16)
+            .filter_map(|code| Key::from_code(code).ok())
+            .find(|key| format!("{:?}", key).to_lowercase() == name)
+            .ok_or(())
     }
 }
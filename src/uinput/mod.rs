@@ -7,20 +7,111 @@ mod key;
 mod uinput_sys;
 
 pub use self::key::Key;
+pub use self::uinput_sys::input_event;
 use self::uinput_sys as ffi;
-use std::io::Write;
-use std::fs::{OpenOptions, File};
-use std::os::unix::io::AsRawFd;
+use nix;
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+use std::fs::{self, OpenOptions, File};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::result;
+use std::str::FromStr;
+
+/// First mouse button code.
+pub const BTN_LEFT: u16 = 0x110;
+/// Last mouse button code. The mouse buttons are, in order, left, right, middle, side, extra,
+/// forward, back and task.
+pub const BTN_TASK: u16 = 0x117;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+
+pub const MSC_SCAN: u16 = 0x04;
+
+pub const BUS_USB: u16 = 0x03;
+pub const BUS_VIRTUAL: u16 = 0x06;
+
+/// Input properties, in the order of their codes.
+const PROPERTIES: [&'static str; 7] = [
+    "pointer", "direct", "buttonpad", "semi-mt", "topbuttonpad", "pointing-stick", "accelerometer",
+];
+
+/// Number of absolute axes uinput can describe.
+const ABS_CNT: usize = 64;
+
+/// uinput version that added UI_DEV_SETUP, UI_ABS_SETUP and UI_GET_VERSION. UI_GET_SYSNAME came
+/// with version 4, which can't be told apart from older ones.
+const VERSION_DEV_SETUP: u32 = 5;
+
+/// Errors from creating or writing to a uinput device.
+#[derive(Debug)]
+pub enum Error {
+    /// /dev/uinput couldn't be opened, usually for lack of permission.
+    Open(io::Error),
+    /// The named ioctl failed.
+    Ioctl(&'static str, nix::Error),
+    /// Writing to the device failed.
+    Write(io::Error),
+    /// An event code swan-ag has no name for.
+    UnknownCode(u16),
+    /// An absolute axis was declared with a code past the last axis, or had its resolution set
+    /// without being enabled.
+    InvalidAxis(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Open(ref e) => write!(f, "failed to open /dev/uinput: {}", e),
+            Error::Ioctl(name, ref e) => write!(f, "{} ioctl failed: {}", name, e),
+            Error::Write(ref e) => write!(f, "failed to write to uinput: {}", e),
+            Error::UnknownCode(code) => write!(f, "unknown key code {}", code),
+            Error::InvalidAxis(code) => write!(f, "absolute axis {} is out of range or not enabled", code),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+pub type Result<T> = result::Result<T, Error>;
 
 mod ioctl {
     const UINPUT_IOCTL_BASE: u8 = 'U' as u8;
 
     ioctl!(none ui_dev_create with UINPUT_IOCTL_BASE, 1);
     ioctl!(none ui_dev_destroy with UINPUT_IOCTL_BASE, 2);
+    ioctl!(write_ptr ui_dev_setup with UINPUT_IOCTL_BASE, 3; super::ffi::uinput_setup);
+    ioctl!(write_ptr ui_abs_setup with UINPUT_IOCTL_BASE, 4; super::ffi::uinput_abs_setup);
+    ioctl!(read_buf ui_get_sysname with UINPUT_IOCTL_BASE, 44; u8);
+    ioctl!(read ui_get_version with UINPUT_IOCTL_BASE, 45; ::libc::c_uint);
+    ioctl!(write_ptr set_prop_bit with UINPUT_IOCTL_BASE, 110; ::libc::c_int);
     ioctl!(write_ptr set_ev_bit with UINPUT_IOCTL_BASE, 100; ::libc::c_int);
     ioctl!(write_ptr set_key_bit with UINPUT_IOCTL_BASE, 101; ::libc::c_int);
     ioctl!(write_ptr set_rel_bit with UINPUT_IOCTL_BASE, 102; ::libc::c_int);
     ioctl!(write_ptr set_abs_bit with UINPUT_IOCTL_BASE, 103; ::libc::c_int);
+    ioctl!(write_ptr set_msc_bit with UINPUT_IOCTL_BASE, 104; ::libc::c_int);
+    ioctl!(write_ptr set_led_bit with UINPUT_IOCTL_BASE, 105; ::libc::c_int);
+    ioctl!(write_ptr set_snd_bit with UINPUT_IOCTL_BASE, 106; ::libc::c_int);
+    ioctl!(write_ptr set_sw_bit with UINPUT_IOCTL_BASE, 109; ::libc::c_int);
+}
+
+/// Returns the event device node of the uinput device with the given sysname.
+fn find_devnode(sysname: &str) -> Option<PathBuf> {
+    let dir = Path::new("/sys/devices/virtual/input").join(sysname);
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .find(|name| name.starts_with("event"))
+        .map(|name| Path::new("/dev/input").join(name))
 }
 
 unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
@@ -30,31 +121,242 @@ unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     )
 }
 
-pub struct UInput {
-    ffi: ffi::uinput_user_dev,
-    ev: ffi::input_event,
-    uinput_device: File,
+/// Bus type, vendor, product and version of a device, written in hex like "0003:046d:c52b:0111".
+/// The version may be left out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
 }
 
-impl UInput {
-    /// Create a uinput handle.
-    pub fn new() -> UInput {
-        let mut name = [0; 80];
-        name[0] = 97; // 'a'
-        let ffi_dev = ffi::uinput_user_dev {
-            name: name,
+impl FromStr for InputId {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<InputId, String> {
+        let ids = s.split(':')
+                   .map(|id| u16::from_str_radix(id.trim(), 16))
+                   .collect::<result::Result<Vec<_>, _>>();
+
+        match ids {
+            Ok(ref ids) if ids.len() == 3 || ids.len() == 4 => {
+                Ok(InputId {
+                    bustype: ids[0],
+                    vendor: ids[1],
+                    product: ids[2],
+                    version: ids.get(3).cloned().unwrap_or(1),
+                })
+            },
+            _ => Err(format!("Expected bus:vendor:product[:version] in hex, like 0003:046d:c52b, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for InputId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}:{:04x}:{:04x}", self.bustype, self.vendor, self.product, self.version)
+    }
+}
+
+/// An input property (INPUT_PROP_*), written as its name like "pointer" or "direct", or its code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Property(pub u16);
+
+impl FromStr for Property {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Property, String> {
+        let s = s.trim().to_lowercase();
+        match PROPERTIES.iter().position(|&name| name == s) {
+            Some(code) => Ok(Property(code as u16)),
+            None => s.parse().map(Property).map_err(|_| {
+                format!("Unknown input property {}. Expected a code or one of {}", s, PROPERTIES.join(", "))
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match PROPERTIES.get(self.0 as usize) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Declares the identity and capabilities of a uinput device before creating it.
+///
+/// Event types are enabled for every code declared with them, other types can be enabled with
+/// `event_type`.
+pub struct UInputBuilder {
+    name: String,
+    id: ffi::input_id,
+    event_types: Vec<EventType>,
+    props: Vec<u16>,
+    keys: Vec<u16>,
+    rels: Vec<u16>,
+    abs: Vec<(u16, ffi::input_absinfo)>,
+    others: Vec<(EventType, u16)>, // Codes of the misc, LED, sound and switch types
+    error: Option<Error>, // First invalid declaration, returned by build
+}
+
+impl UInputBuilder {
+    pub fn new() -> UInputBuilder {
+        UInputBuilder {
+            name: "swan-ag virtual input".to_string(),
             id: ffi::input_id {
-                bustype: 0x03, // BUS_USB
-                vendor: 1,
-                product: 1,
+                bustype: BUS_VIRTUAL,
+                vendor: 0,
+                product: 0,
                 version: 1
             },
-            ff_effects_max: 0,
-            absmax: [0; 64],
-            absmin: [0; 64],
-            absfuzz: [0; 64],
-            absflat: [0; 64],
+            event_types: Vec::new(),
+            props: Vec::new(),
+            keys: Vec::new(),
+            rels: Vec::new(),
+            abs: Vec::new(),
+            others: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Name of the device. Names longer than 79 bytes are truncated.
+    pub fn name(mut self, name: &str) -> UInputBuilder {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn bustype(mut self, bustype: u16) -> UInputBuilder {
+        self.id.bustype = bustype;
+        self
+    }
+
+    pub fn vendor(mut self, vendor: u16) -> UInputBuilder {
+        self.id.vendor = vendor;
+        self
+    }
+
+    pub fn product(mut self, product: u16) -> UInputBuilder {
+        self.id.product = product;
+        self
+    }
+
+    pub fn version(mut self, version: u16) -> UInputBuilder {
+        self.id.version = version;
+        self
+    }
+
+    /// Set the bus type, vendor, product and version at once.
+    pub fn id(self, id: InputId) -> UInputBuilder {
+        self.bustype(id.bustype).vendor(id.vendor).product(id.product).version(id.version)
+    }
+
+    /// Set an input property, telling userspace how to treat the device.
+    pub fn prop(mut self, prop: Property) -> UInputBuilder {
+        if !self.props.contains(&prop.0) {
+            self.props.push(prop.0);
+        }
+        self
+    }
+
+    pub fn event_type(mut self, kind: EventType) -> UInputBuilder {
+        if !self.event_types.contains(&kind) {
+            self.event_types.push(kind);
+        }
+        self
+    }
+
+    /// Enable a key or button code.
+    pub fn key(mut self, code: u16) -> UInputBuilder {
+        self.keys.push(code);
+        self.event_type(EventType::EV_KEY)
+    }
+
+    /// Enable a range of key or button codes, excluding the end.
+    pub fn keys(mut self, codes: ::std::ops::Range<u16>) -> UInputBuilder {
+        self.keys.extend(codes);
+        self.event_type(EventType::EV_KEY)
+    }
+
+    /// Enable a relative axis.
+    pub fn rel(mut self, code: u16) -> UInputBuilder {
+        self.rels.push(code);
+        self.event_type(EventType::EV_REL)
+    }
+
+    /// Enable a misc code, like MSC_SCAN for the scancodes keyboards send along with keys.
+    pub fn msc(mut self, code: u16) -> UInputBuilder {
+        self.others.push((EventType::EV_MSC, code));
+        self.event_type(EventType::EV_MSC)
+    }
+
+    /// Enable an LED, like LED_CAPSL.
+    pub fn led(mut self, code: u16) -> UInputBuilder {
+        self.others.push((EventType::EV_LED, code));
+        self.event_type(EventType::EV_LED)
+    }
+
+    /// Enable a sound, like SND_BELL.
+    pub fn snd(mut self, code: u16) -> UInputBuilder {
+        self.others.push((EventType::EV_SND, code));
+        self.event_type(EventType::EV_SND)
+    }
+
+    /// Enable a switch, like SW_LID.
+    pub fn sw(mut self, code: u16) -> UInputBuilder {
+        self.others.push((EventType::EV_SW, code));
+        self.event_type(EventType::EV_SW)
+    }
+
+    /// Enable an absolute axis reporting values from min to max. Codes past the last axis make
+    /// `build` fail.
+    pub fn abs(mut self, code: u16, min: i32, max: i32) -> UInputBuilder {
+        if code as usize >= ABS_CNT {
+            self.fail(Error::InvalidAxis(code));
+            return self;
+        }
+        self.abs.push((code, ffi::input_absinfo {
+            value: 0,
+            minimum: min,
+            maximum: max,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        }));
+        self.event_type(EventType::EV_ABS)
+    }
+
+    /// Set the resolution of an absolute axis enabled with `abs`, in units per millimeter (units
+    /// per radian for rotational axes). Kernels older than uinput version 5 ignore it. Axes that
+    /// aren't enabled make `build` fail.
+    pub fn resolution(mut self, code: u16, resolution: i32) -> UInputBuilder {
+        let enabled = match self.abs.iter_mut().find(|a| a.0 == code) {
+            Some(axis) => {
+                axis.1.resolution = resolution;
+                true
+            },
+            None => false,
         };
+        if !enabled {
+            self.fail(Error::InvalidAxis(code));
+        }
+        self
+    }
+
+    /// Remember an invalid declaration for `build` to return, keeping the first one.
+    fn fail(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Create the device.
+    pub fn build(mut self) -> Result<UInput> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
 
         let ev = ffi::input_event {
             time: ffi::timeval {
@@ -71,158 +373,296 @@ impl UInput {
                                  .read(true)
                                  .write(true)
                                  .open("/dev/uinput") // or /dev/input/uinput
-                                 .expect("Failed to open uinput");
+                                 .map_err(Error::Open)?;
         let fd = uinput_device.as_raw_fd();
 
-        // Register all relevant events
+        // Kernels before uinput version 5 don't know UI_GET_VERSION.
+        let mut version = 0;
+        let version = match unsafe { ioctl::ui_get_version(fd, &mut version) } {
+            Ok(_) => version,
+            Err(_) => 0,
+        };
+
+        // Register all declared events
         unsafe {
-            ioctl::set_ev_bit(fd, (EventType::EV_KEY as u8) as *const ::libc::c_int).expect("ioctl failed.");
-            ioctl::set_key_bit(fd, 0x110 as *const _).expect("ioctl failed."); // BTN_LEFT
-            ioctl::set_key_bit(fd, 0x111 as *const _).expect("ioctl failed."); // BTN_RIGHT
-            /*
-            ioctl::set_key_bit(fd, 0x112 as *const _).expect("ioctl failed."); // BTN_MIDDLE
-            ioctl::set_key_bit(fd, 0x115 as *const _).expect("ioctl failed."); // BTN_FORWARD
-            ioctl::set_key_bit(fd, 0x116 as *const _).expect("ioctl failed."); // BTN_BACK
-            */
-            for i in 1..150u8 {
-                ioctl::set_key_bit(fd, i as *const _).expect("ioctl failed."); // Most of the keyboard keys.
+            for &kind in &self.event_types {
+                ioctl::set_ev_bit(fd, (kind as u8) as *const ::libc::c_int)
+                    .map_err(|e| Error::Ioctl("UI_SET_EVBIT", e))?;
+            }
+            for &prop in &self.props {
+                ioctl::set_prop_bit(fd, prop as *const _).map_err(|e| Error::Ioctl("UI_SET_PROPBIT", e))?;
+            }
+            for &code in &self.keys {
+                ioctl::set_key_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_KEYBIT", e))?;
+            }
+            for &code in &self.rels {
+                ioctl::set_rel_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_RELBIT", e))?;
             }
-            ioctl::set_ev_bit(fd, (EventType::EV_REL as u8) as *const ::libc::c_int).expect("ioctl failed.");
-            ioctl::set_rel_bit(fd, 0 as *const _).expect("ioctl failed."); // REL_X
-            ioctl::set_rel_bit(fd, 1 as *const _).expect("ioctl failed."); // REL_Y
-            /*
-            ioctl::set_ev_bit(fd, (EventType::EV_ABS as u8) as *const ::libc::c_int).expect("ioctl failed.");
-            ioctl::set_abs_bit(fd, 0 as *const _).expect("ioctl failed."); // ABS_X
-            ioctl::set_abs_bit(fd, 1 as *const _).expect("ioctl failed."); // ABS_Y
-            */
+            for &(code, _) in &self.abs {
+                ioctl::set_abs_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_ABSBIT", e))?;
+            }
+            for &(kind, code) in &self.others {
+                let result = match kind {
+                    EventType::EV_MSC => ioctl::set_msc_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_MSCBIT", e)),
+                    EventType::EV_LED => ioctl::set_led_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_LEDBIT", e)),
+                    EventType::EV_SND => ioctl::set_snd_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_SNDBIT", e)),
+                    _ => ioctl::set_sw_bit(fd, code as *const _).map_err(|e| Error::Ioctl("UI_SET_SWBIT", e)),
+                };
+                result?;
+            }
+        }
 
-            let raw_dev = any_as_u8_slice(&ffi_dev);
-            uinput_device.write_all(raw_dev).expect("Write failed.");
+        if version >= VERSION_DEV_SETUP {
+            self.setup(fd)?;
+        } else {
+            self.legacy_setup(&mut uinput_device)?;
+        }
 
-            ioctl::ui_dev_create(fd).expect("uidev create failed.");
+        unsafe {
+            ioctl::ui_dev_create(fd).map_err(|e| Error::Ioctl("UI_DEV_CREATE", e))?;
         }
 
-        UInput {
-            ffi: ffi_dev,
+        // UI_GET_SYSNAME is tried whatever the version, as version 4 has it but no UI_GET_VERSION.
+        let mut buf = [0u8; 64];
+        let sysname = match unsafe { ioctl::ui_get_sysname(fd, &mut buf) } {
+            Ok(_) => {
+                let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+                Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+            },
+            Err(_) => None,
+        };
+
+        // The event node is created along with the device, so it can be looked up right away.
+        let devnode = sysname.as_ref().and_then(|sysname| find_devnode(sysname));
+
+        Ok(UInput {
             ev: ev,
             uinput_device: uinput_device,
+            held: Vec::new(),
+            name: self.name,
+            version: version,
+            sysname: sysname,
+            devnode: devnode,
+        })
+    }
+
+    /// Returns the name as a C string, truncated to fit.
+    fn c_name(&self) -> [::std::os::raw::c_char; 80] {
+        let mut name = [0; 80];
+        for (dst, &src) in name.iter_mut().zip(self.name.as_bytes().iter().take(79)) {
+            *dst = src as ::std::os::raw::c_char;
+        }
+        name
+    }
+
+    /// Describe the device with UI_DEV_SETUP and UI_ABS_SETUP.
+    fn setup(&self, fd: RawFd) -> Result<()> {
+        let setup = ffi::uinput_setup {
+            id: self.id,
+            name: self.c_name(),
+            ff_effects_max: 0,
+        };
+
+        unsafe {
+            ioctl::ui_dev_setup(fd, &setup).map_err(|e| Error::Ioctl("UI_DEV_SETUP", e))?;
+            for &(code, absinfo) in &self.abs {
+                let abs_setup = ffi::uinput_abs_setup {
+                    code: code,
+                    absinfo: absinfo,
+                };
+                ioctl::ui_abs_setup(fd, &abs_setup).map_err(|e| Error::Ioctl("UI_ABS_SETUP", e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describe the device by writing a uinput_user_dev, for kernels without UI_DEV_SETUP.
+    fn legacy_setup(&self, uinput_device: &mut File) -> Result<()> {
+        let mut absmin = [0; ABS_CNT];
+        let mut absmax = [0; ABS_CNT];
+        for &(code, ref absinfo) in &self.abs {
+            absmin[code as usize] = absinfo.minimum;
+            absmax[code as usize] = absinfo.maximum;
+        }
+        let ffi_dev = ffi::uinput_user_dev {
+            name: self.c_name(),
+            id: self.id,
+            ff_effects_max: 0,
+            absmax: absmax,
+            absmin: absmin,
+            absfuzz: [0; ABS_CNT],
+            absflat: [0; ABS_CNT],
+        };
+
+        unsafe {
+            let raw_dev = any_as_u8_slice(&ffi_dev);
+            uinput_device.write_all(raw_dev).map_err(Error::Write)
         }
     }
+}
 
-    pub fn key_press(&mut self, key: Key) {
-        self.ev.kind = EventType::EV_KEY as u16;
+pub struct UInput {
+    ev: ffi::input_event,
+    uinput_device: File,
+    held: Vec<u16>, // Codes of the keys and buttons pressed and not yet released
+    name: String,
+    version: u32,
+    sysname: Option<String>,
+    devnode: Option<PathBuf>,
+}
+
+impl UInput {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The uinput version of the kernel, or 0 before version 5.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The name of the device in sysfs, like "input42". Unknown before uinput version 4 (Linux 3.15).
+    pub fn sysname(&self) -> Option<&str> {
+        self.sysname.as_ref().map(|s| s.as_str())
+    }
+
+    /// The event device node of the device, like /dev/input/event5. libinput and udev name the
+    /// device after it. Unknown before uinput version 4.
+    pub fn devnode(&self) -> Option<&Path> {
+        self.devnode.as_ref().map(|p| p.as_path())
+    }
+
+    /// Write an event of any type. Events only take effect after the next sync.
+    pub fn emit(&mut self, kind: EventType, code: u16, value: i32) -> Result<()> {
+        self.ev.kind = kind as u16;
+        self.ev.code = code;
+        self.ev.value = value;
+        self.write()?;
+
+        if kind == EventType::EV_KEY {
+            self.held.retain(|&c| c != code);
+            if value != 0 {
+                self.held.push(code);
+            }
+        }
+        Ok(())
+    }
+
+    /// Release every key and button that was pressed and not released, and sync.
+    pub fn release_all(&mut self) -> Result<()> {
+        if self.held.is_empty() {
+            return Ok(());
+        }
+
+        // Releasing removes the code from held.
+        while let Some(&code) = self.held.last() {
+            self.emit(EventType::EV_KEY, code, 0)?;
+        }
+        self.sync()
+    }
+
+    pub fn key_press(&mut self, key: Key) -> Result<()> {
         let val: u8 = key.into();
-        self.ev.code = val as u16;
-        self.ev.value = 1;
-        self.write();
+        self.emit(EventType::EV_KEY, val as u16, 1)
     }
 
-    pub fn key_release(&mut self, key: Key) {
-        self.ev.kind = EventType::EV_KEY as u16;
+    pub fn key_release(&mut self, key: Key) -> Result<()> {
         let val: u8 = key.into();
-        self.ev.code = val as u16;
-        self.ev.value = 0;
-        self.write();
+        self.emit(EventType::EV_KEY, val as u16, 0)
+    }
+    pub fn key_click(&mut self, key: Key) -> Result<()> {
+        self.key_press(key)?;
+        self.key_release(key)
     }
-    pub fn key_click(&mut self, key: Key) {
-        self.key_press(key);
-        self.key_release(key);
+
+    /// Press the mouse button with the given code, BTN_LEFT to BTN_TASK.
+    pub fn button_press(&mut self, button: u16) -> Result<()> {
+        self.emit(EventType::EV_KEY, button, 1)
+    }
+
+    /// Release the mouse button with the given code, BTN_LEFT to BTN_TASK.
+    pub fn button_release(&mut self, button: u16) -> Result<()> {
+        self.emit(EventType::EV_KEY, button, 0)
     }
 
-    pub fn btn_left_press(&mut self) {
-        self.ev.kind = EventType::EV_KEY as u16;
-        self.ev.code = 0x110 as u16;
-        self.ev.value = 1;
-        self.write();
+    pub fn sync(&mut self) -> Result<()> {
+        self.emit(EventType::EV_SYN, 0, 0)
     }
 
-    pub fn btn_left_release(&mut self) {
-        self.ev.kind = EventType::EV_KEY as u16;
-        self.ev.code = 0x110 as u16;
-        self.ev.value = 0;
-        self.write();
+    pub fn rel_x(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_X, val)
     }
 
-    pub fn btn_right_press(&mut self) {
-        self.ev.kind = EventType::EV_KEY as u16;
-        self.ev.code = 0x111 as u16;
-        self.ev.value = 1;
-        self.write();
+    pub fn rel_y(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_Y, val)
     }
 
-    pub fn btn_right_release(&mut self) {
-        self.ev.kind = EventType::EV_KEY as u16;
-        self.ev.code = 0x111 as u16;
-        self.ev.value = 0;
-        self.write();
+    pub fn wheel(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_WHEEL, val)
     }
 
-    pub fn sync(&mut self) {
-        self.ev.kind = EventType::EV_SYN as u16;
-        self.ev.code = 0;
-        self.ev.value = 0;
-        self.write();
+    pub fn hwheel(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_HWHEEL, val)
     }
 
-    pub fn rel_x(&mut self, val: i32) {
-        self.ev.kind = EventType::EV_REL as u16;
-        self.ev.code = 0;
-        self.ev.value = val;
-        self.write();
+    /// Scroll in 120ths of a wheel click.
+    pub fn wheel_hi_res(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_WHEEL_HI_RES, val)
     }
 
-    pub fn rel_y(&mut self, val: i32) {
-        self.ev.kind = EventType::EV_REL as u16;
-        self.ev.code = 1;
-        self.ev.value = val;
-        self.write();
+    /// Scroll horizontally in 120ths of a wheel click.
+    pub fn hwheel_hi_res(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_REL, REL_HWHEEL_HI_RES, val)
     }
 
-    pub fn abs_x(&mut self, val: i32) {
-        self.ev.kind = EventType::EV_ABS as u16;
-        self.ev.code = 0;
-        self.ev.value = val;
-        self.write();
+    pub fn abs_x(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_ABS, ABS_X, val)
     }
 
-    pub fn abs_y(&mut self, val: i32) {
-        self.ev.kind = EventType::EV_ABS as u16;
-        self.ev.code = 1;
-        self.ev.value = val;
-        self.write();
+    pub fn abs_y(&mut self, val: i32) -> Result<()> {
+        self.emit(EventType::EV_ABS, ABS_Y, val)
     }
 
-    fn write(&mut self) {
+    fn write(&mut self) -> Result<()> {
         unsafe {
             let raw_ev = any_as_u8_slice(&self.ev);
-            self.uinput_device.write_all(raw_ev).expect("Write failed.");
+            self.uinput_device.write_all(raw_ev).map_err(Error::Write)
         }
     }
 }
 
 impl Drop for UInput {
     fn drop(&mut self) {
+        // Keys still held when the device goes away would stay down until pressed again.
+        if let Err(e) = self.release_all() {
+            eprintln!("Failed to release held keys: {}", e);
+        }
+
         let fd = self.uinput_device.as_raw_fd();
         unsafe {
-            ioctl::ui_dev_destroy(fd).expect("uidev destroy failed.");
+            if let Err(e) = ioctl::ui_dev_destroy(fd) {
+                eprintln!("Failed to destroy uinput device: {}", Error::Ioctl("UI_DEV_DESTROY", e));
+            }
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EventType {
     EV_SYN = 0x00,
     EV_KEY = 0x01,
     EV_REL = 0x02,
     EV_ABS = 0x03,
-    /*
     EV_MSC = 0x04,
-    EV_SW =	0x05,
+    EV_SW = 0x05,
     EV_LED = 0x11,
     EV_SND = 0x12,
     EV_REP = 0x14,
-    EV_FF =	0x15,
+    EV_FF = 0x15,
     EV_PWR = 0x16,
     EV_FF_STATUS = 0x17,
+    /*
     EV_MAX = 0x1f,
     EV_CNT = 0x20,
     */
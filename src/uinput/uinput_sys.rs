@@ -17,6 +17,31 @@ pub struct uinput_user_dev {
 }
 
 #[repr(C)]
+pub struct uinput_setup {
+    pub id: input_id,
+    pub name: [::std::os::raw::c_char; 80usize],
+    pub ff_effects_max: __u32,
+}
+
+#[repr(C)]
+pub struct uinput_abs_setup {
+    pub code: __u16,
+    pub absinfo: input_absinfo,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct input_absinfo {
+    pub value: __s32,
+    pub minimum: __s32,
+    pub maximum: __s32,
+    pub fuzz: __s32,
+    pub flat: __s32,
+    pub resolution: __s32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
 pub struct input_id {
     pub bustype: __u16,
     pub vendor: __u16,